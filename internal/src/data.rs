@@ -1,25 +1,62 @@
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 use arena::{Arena, Key};
-use std::{collections::HashMap, rc::Rc};
+use naga::ResourceBinding;
+use purs::ReflectionCache;
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::SystemTime,
+};
 use wgpu::{
-    BlendState, Device, FragmentState, MultisampleState, PrimitiveState, RenderPipeline,
-    RenderPipelineDescriptor, Sampler, VertexState,
+    Buffer, BufferDescriptor, Device, FragmentState, MultisampleState, PrimitiveState, Queue,
+    RenderPipeline, RenderPipelineDescriptor, Sampler, SamplerDescriptor, VertexBufferLayout,
+    VertexState,
 };
 
 use crate::types::{
-    framebuffer::{FrameBuffer, RenderAttachment},
-    pipeline::PipelineRequirements,
-    shader::Shader,
-    texture::Texture,
+    framebuffer::TargetFormats,
+    pipeline::{
+        color_targets, depth_stencil_state, EntryPoints, PipelineRequirements, RenderState,
+    },
+    shader::{shader_from_reflection, Shader},
+    texture::{ColorSpace, Texture, TextureMut},
     vertex::Vertex,
 };
 
+/// Creates a shader from WGSL `source`, binding the buffers at `dynamic_offsets` with a
+/// dynamic offset. Its bindings are only checked against what's bound when it's drawn with,
+/// as they can be set per batch.
+fn drawable_shader(
+    device: &Device,
+    reflections: &mut ReflectionCache,
+    source: &str,
+    dynamic_offsets: &[ResourceBinding],
+) -> Result<Shader> {
+    let mut reflection = reflections.reflect(source)?;
+    reflection.set_dynamic_offsets(dynamic_offsets)?;
+    shader_from_reflection(device, source, reflection)
+}
+
+/// Where a shader was loaded from, so it can be reloaded when the file changes.
+#[derive(Debug)]
+struct ShaderSource {
+    path: PathBuf,
+    modified: SystemTime,
+    dynamic_offsets: Vec<ResourceBinding>,
+}
+
 #[derive(Debug, Default)]
 pub struct InternalData {
     pipelines: HashMap<Key<Shader>, HashMap<PipelineRequirements, Rc<RenderPipeline>>>,
     shaders: Arena<Shader>,
+    shader_names: HashMap<String, Key<Shader>>,
+    shader_sources: HashMap<Key<Shader>, ShaderSource>,
+    reflections: ReflectionCache,
     textures: Arena<Texture>,
     samplers: Arena<Sampler>,
+    buffers: Arena<Buffer>,
 }
 
 impl InternalData {
@@ -29,32 +66,278 @@ impl InternalData {
         data
     }
 
-    pub fn create_shader(&mut self) -> Result<Key<Shader>> {
-        todo!()
+    /// Loads a WGSL shader and registers it under `name`.
+    ///
+    /// If a shader already has that name it is replaced in place, so its key stays valid, and
+    /// the pipelines built from it are dropped. On error the old shader is left untouched.
+    pub fn create_shader(
+        &mut self,
+        device: &Device,
+        name: &str,
+        wgsl_source: &str,
+    ) -> Result<Key<Shader>> {
+        self.create_shader_with_dynamic_offsets(device, name, wgsl_source, &[])
+    }
+
+    /// Like [`create_shader`](Self::create_shader), binding the buffers at `dynamic_offsets`
+    /// with a dynamic offset. The offset of the [`Resource::Buffer`] set for a batch is then
+    /// passed when drawing instead of being baked into the bind group, and so is the transform
+    /// matrix's if it's one of them.
+    ///
+    /// [`Resource::Buffer`]: crate::types::resource::Resource::Buffer
+    pub fn create_shader_with_dynamic_offsets(
+        &mut self,
+        device: &Device,
+        name: &str,
+        wgsl_source: &str,
+        dynamic_offsets: &[ResourceBinding],
+    ) -> Result<Key<Shader>> {
+        let shader = drawable_shader(device, &mut self.reflections, wgsl_source, dynamic_offsets)?;
+
+        if let Some(&key) = self.shader_names.get(name) {
+            if self.shaders.contains(key) {
+                self.replace_shader(key, shader);
+                // stop watching the file it might have come from.
+                self.shader_sources.remove(&key);
+                return Ok(key);
+            }
+        }
+
+        let key = self.shaders.insert(shader);
+        self.shader_names.insert(name.to_owned(), key);
+        Ok(key)
+    }
+
+    /// Loads a WGSL shader from a file and registers it under `name`, like
+    /// [`create_shader`](Self::create_shader). The file is then watched by
+    /// [`reload_shaders`](Self::reload_shaders).
+    pub fn load_shader_file(
+        &mut self,
+        device: &Device,
+        name: &str,
+        path: impl Into<PathBuf>,
+    ) -> Result<Key<Shader>> {
+        self.load_shader_file_with_dynamic_offsets(device, name, path, &[])
+    }
+
+    /// Like [`load_shader_file`](Self::load_shader_file), with dynamic offsets as in
+    /// [`create_shader_with_dynamic_offsets`](Self::create_shader_with_dynamic_offsets), which
+    /// are kept when it's reloaded.
+    pub fn load_shader_file_with_dynamic_offsets(
+        &mut self,
+        device: &Device,
+        name: &str,
+        path: impl Into<PathBuf>,
+        dynamic_offsets: &[ResourceBinding],
+    ) -> Result<Key<Shader>> {
+        let path = path.into();
+        let modified = fs::metadata(&path)?.modified()?;
+        let source = fs::read_to_string(&path)
+            .with_context(|| format!("Could not read shader '{}'", path.display()))?;
+
+        let key =
+            self.create_shader_with_dynamic_offsets(device, name, &source, dynamic_offsets)?;
+        self.shader_sources.insert(
+            key,
+            ShaderSource {
+                path,
+                modified,
+                dynamic_offsets: dynamic_offsets.to_vec(),
+            },
+        );
+        Ok(key)
+    }
+
+    /// Reloads every shader loaded from a file that has changed since it was last loaded,
+    /// returning the keys of the ones that were swapped.
+    ///
+    /// A shader that fails to load keeps its previous version, and the error is logged.
+    pub fn reload_shaders(&mut self, device: &Device) -> Vec<Key<Shader>> {
+        let mut reloaded = vec![];
+
+        for (&key, source) in self.shader_sources.iter_mut() {
+            // the file might be missing for a moment while an editor saves it.
+            let Ok(modified) = fs::metadata(&source.path).and_then(|m| m.modified()) else {
+                continue;
+            };
+            if modified == source.modified {
+                continue;
+            }
+            source.modified = modified;
+
+            let shader = fs::read_to_string(&source.path)
+                .map_err(Into::into)
+                .and_then(|wgsl_source| {
+                    drawable_shader(
+                        device,
+                        &mut self.reflections,
+                        &wgsl_source,
+                        &source.dynamic_offsets,
+                    )
+                });
+
+            match shader {
+                Ok(shader) => {
+                    log::info!("reloaded shader '{}'", source.path.display());
+                    reloaded.push((key, shader));
+                }
+                Err(e) => log::error!(
+                    "failed to reload shader '{}', keeping the old one: {e:#}",
+                    source.path.display()
+                ),
+            }
+        }
+
+        reloaded
+            .into_iter()
+            .map(|(key, shader)| {
+                self.replace_shader(key, shader);
+                key
+            })
+            .collect()
+    }
+
+    /// Loads shader reflection saved by [`save_reflection_cache`](Self::save_reflection_cache),
+    /// so shaders whose source hasn't changed skip parsing and validation.
+    pub fn load_reflection_cache(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        self.reflections = ReflectionCache::load(path)?;
+        Ok(())
+    }
+
+    /// Saves the reflection of the shaders currently loaded, dropping that of older versions
+    /// of them.
+    pub fn save_reflection_cache(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let loaded: HashSet<u64> = self
+            .shaders
+            .iter()
+            .map(|shader| shader.source_hash)
+            .collect();
+        self.reflections.retain(|hash| loaded.contains(&hash));
+        self.reflections.save(path)
+    }
+
+    /// Swaps the shader behind `key`, dropping the pipelines built from the old one.
+    fn replace_shader(&mut self, key: Key<Shader>, shader: Shader) {
+        self.shaders[key] = shader;
+        self.pipelines.remove(&key);
     }
 
-    pub fn get_shader(&mut self) -> Result<Key<Shader>> {
-        todo!()
+    /// Looks up a shader by the name it was created with.
+    pub fn get_shader(&self, name: &str) -> Result<Key<Shader>> {
+        self.shader_names
+            .get(name)
+            .copied()
+            .ok_or_else(|| anyhow!("Could not find shader named '{name}'"))
+    }
+
+    pub(crate) fn shader(&self, key: Key<Shader>) -> Option<&Shader> {
+        self.shaders.get(key)
+    }
+
+    /// Decodes an image file held in `bytes` (PNG, JPEG, ...) into a new texture.
+    pub fn create_texture(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        bytes: &[u8],
+        color_space: ColorSpace,
+    ) -> Result<Key<Texture>> {
+        let texture = Texture::from_bytes(device, queue, bytes, color_space)?;
+        Ok(self.textures.insert(texture))
+    }
+
+    /// Loads the image file at `path` into a new texture.
+    pub fn load_texture_file(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        path: impl AsRef<Path>,
+        color_space: ColorSpace,
+    ) -> Result<Key<Texture>> {
+        let path = path.as_ref();
+        let image = image::open(path)
+            .with_context(|| format!("Could not load texture '{}'", path.display()))?;
+        let texture = Texture::from_image(device, queue, &image.into_rgba8(), color_space)
+            .with_context(|| format!("Could not load texture '{}'", path.display()))?;
+        Ok(self.textures.insert(texture))
+    }
+
+    /// Uploads `image` into a new texture.
+    pub fn create_texture_from_image(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        image: &image::RgbaImage,
+        color_space: ColorSpace,
+    ) -> Result<Key<Texture>> {
+        let texture = Texture::from_image(device, queue, image, color_space)?;
+        Ok(self.textures.insert(texture))
+    }
+
+    /// Gets a texture to update its contents or size.
+    pub fn texture_mut<'a>(
+        &'a mut self,
+        device: &'a Device,
+        queue: &'a Queue,
+        key: Key<Texture>,
+    ) -> Result<TextureMut<'a>> {
+        let texture = self
+            .textures
+            .get_mut(key)
+            .ok_or_else(|| anyhow!("Could not find texture with key '{:?}'", key))?;
+        Ok(TextureMut::new(texture, device, queue))
+    }
+
+    pub(crate) fn texture(&self, key: Key<Texture>) -> Option<&Texture> {
+        self.textures.get(key)
+    }
+
+    /// Creates a sampler, to be bound with
+    /// [`RenderPassBuilder::set_resource`](crate::renderpass::RenderPassBuilder::set_resource).
+    pub fn create_sampler(
+        &mut self,
+        device: &Device,
+        descriptor: &SamplerDescriptor,
+    ) -> Key<Sampler> {
+        self.samplers.insert(device.create_sampler(descriptor))
+    }
+
+    pub(crate) fn sampler(&self, key: Key<Sampler>) -> Option<&Sampler> {
+        self.samplers.get(key)
+    }
+
+    /// Creates a uniform or storage buffer, to be bound with
+    /// [`RenderPassBuilder::set_resource`](crate::renderpass::RenderPassBuilder::set_resource).
+    pub fn create_buffer(&mut self, device: &Device, descriptor: &BufferDescriptor) -> Key<Buffer> {
+        self.buffers.insert(device.create_buffer(descriptor))
+    }
+
+    /// The buffer behind `key`, to write to it.
+    pub fn buffer(&self, key: Key<Buffer>) -> Option<&Buffer> {
+        self.buffers.get(key)
     }
 
     pub fn get_pipeline(
         &mut self,
         device: &Device,
-        render_attachment: &RenderAttachment,
+        target: &TargetFormats,
         key: Key<Shader>,
         primitive: PrimitiveState,
+        entry_points: &EntryPoints,
+        state: &RenderState,
     ) -> Result<Rc<RenderPipeline>> {
         let shader = self
             .shaders
             .get(key)
             .ok_or_else(|| anyhow!("Could not find shader with key '{:?}'", key))?;
-
-        let mut targets: [Option<wgpu::ColorTargetState>; FrameBuffer::MAXCOLORATTACHMENTS] =
-            Default::default();
-
-        (0..FrameBuffer::MAXCOLORATTACHMENTS).for_each(|n| {
-            targets[n] = Some(render_attachment.color_target_state(Some(BlendState::REPLACE)))
-        });
+        let vertex_entry_point = shader.vertex_entry_point(entry_points.vertex.as_deref())?;
+        let fragment = shader.reflection.entry_point(
+            naga::ShaderStage::Fragment,
+            entry_points.fragment.as_deref(),
+        )?;
+        let fragment_entry_point = fragment.name.as_str();
+        let targets = color_targets(fragment, &target.color, state.blend, device.features())?;
+        let depth_stencil = depth_stencil_state(target, state)?;
 
         Ok(self
             .pipelines
@@ -63,6 +346,10 @@ impl InternalData {
             .entry(PipelineRequirements {
                 primitive,
                 targets: targets.clone(),
+                depth_stencil: depth_stencil.clone(),
+                sample_count: target.sample_count,
+                vertex_entry_point: vertex_entry_point.to_owned(),
+                fragment_entry_point: fragment_entry_point.to_owned(),
             })
             .or_insert_with(|| {
                 Rc::new(device.create_render_pipeline(&RenderPipelineDescriptor {
@@ -70,20 +357,23 @@ impl InternalData {
                     layout: Some(&shader.pipeline_layout),
                     vertex: VertexState {
                         module: &shader.module,
-                        entry_point: "vertex",
-                        buffers: &[Vertex::BUFFER_LAYOUT],
+                        entry_point: vertex_entry_point,
+                        buffers: &[VertexBufferLayout {
+                            attributes: &shader.vertex_attributes[vertex_entry_point],
+                            ..Vertex::BUFFER_LAYOUT
+                        }],
                     },
                     primitive,
-                    depth_stencil: None,
+                    depth_stencil,
                     multisample: MultisampleState {
-                        count: 1,
+                        count: target.sample_count,
                         mask: !0,
                         alpha_to_coverage_enabled: false,
                     },
                     fragment: Some(FragmentState {
                         module: &shader.module,
-                        entry_point: "fragment",
-                        targets: &targets,
+                        entry_point: fragment_entry_point,
+                        targets: &targets[..target.color.len()],
                     }),
                     multiview: None,
                 }))
@@ -91,3 +381,36 @@ impl InternalData {
             .clone())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn main_shader_is_drawable() {
+        // everything checked before drawing with no resources set that doesn't need a device.
+        let reflection = ReflectionCache::default()
+            .reflect(include_str!("../../shaders/main.wgsl"))
+            .unwrap();
+        for (group, bindings) in reflection.bind_groups.iter().enumerate() {
+            for binding in bindings {
+                assert!(purs::builtin_binding(group, &binding.entry));
+            }
+        }
+
+        let vertex = reflection
+            .entry_point(naga::ShaderStage::Vertex, None)
+            .unwrap();
+        purs::gen::vertex_attributes(vertex, &Vertex::BUFFER_LAYOUT).unwrap();
+        let fragment = reflection
+            .entry_point(naga::ShaderStage::Fragment, None)
+            .unwrap();
+        color_targets(
+            fragment,
+            &[Some(wgpu::TextureFormat::Bgra8UnormSrgb)],
+            Default::default(),
+            wgpu::Features::empty(),
+        )
+        .unwrap();
+    }
+}
@@ -1,17 +1,32 @@
 use crate::{
+    renderpass::{
+        clamp_scissor, FrameStats, RenderPass, RenderPassBuilder, RenderPassData, RenderTarget,
+    },
     types::{
-        shader::{load_shader, Shader},
-        Color,
+        buffer::StreamBuffer,
+        framebuffer::{Attachment, FrameBuffer, RenderAttachment, TargetFormats},
+        resource::{check_sample_type, Resource},
+        shader::Shader,
+        texture::{check_sample_count, new_wgpu_texture, Texture},
     },
     InternalData,
 };
-use anyhow::Result;
-use glam::UVec2;
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use arena::Key;
+use glam::{Mat4, UVec2};
 use pollster::FutureExt;
-use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle};
+use std::{
+    collections::BTreeMap,
+    ops::Range,
+    path::PathBuf,
+    rc::Rc,
+    time::{Duration, Instant},
+};
 use wgpu::{
-    Adapter, Device, Features, InstanceDescriptor, Queue, RenderPassDescriptor, Surface,
-    SurfaceConfiguration,
+    Adapter, BindGroupDescriptor, BindGroupEntry, BindingResource, BufferAddress, BufferBinding,
+    BufferSize, BufferUsages, Device, Features, InstanceDescriptor, LoadOp, Operations,
+    PrimitiveState, Queue, RenderPassDescriptor, Sampler, Surface, SurfaceConfiguration,
+    TextureView,
 };
 use window::WindowTrait;
 
@@ -23,11 +38,31 @@ pub struct Internal {
     queue: Queue,
     surface: Surface,
     config: SurfaceConfiguration,
-    default_shader: Shader,
-    default_pipeline: wgpu::RenderPipeline,
+    /// How many samples the swapchain pass renders with.
+    sample_count: u32,
+    /// What the swapchain pass renders into when it is multisampled, before being resolved.
+    multisampled_swapchain: Option<wgpu::Texture>,
+    swapchain_depth_format: Option<wgpu::TextureFormat>,
+    swapchain_depth: Option<wgpu::Texture>,
+    default_texture: TextureView,
+    default_sampler: Sampler,
+    pass: RenderPassBuilder,
+    passes: Vec<RenderPass>,
+    stats: FrameStats,
+    vertex_buffer: StreamBuffer,
+    index_buffer: StreamBuffer,
+    uniform_buffer: StreamBuffer,
+    last_shader_poll: Instant,
+    /// Where `main.wgsl` is read from instead of the copy built into the library.
+    main_shader_path: Option<PathBuf>,
 }
 
 impl Internal {
+    /// Name the default shader is registered under in [`InternalData`].
+    pub const MAIN_SHADER: &'static str = "main";
+    /// How often shaders loaded from files are checked for changes.
+    const SHADER_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
     pub fn new<W>(window: &W) -> Result<Internal>
     where
         W: WindowTrait,
@@ -55,7 +90,13 @@ impl Internal {
             println!("Using {} ({:?})", adapter_info.name, adapter_info.backend);
 
             let adapter_features = adapter.features();
-            let optional_features = Features::POLYGON_MODE_LINE | Features::POLYGON_MODE_POINT;
+            // binding arrays are only needed by shaders that use them, and `create_shader`
+            // reports it when they're missing.
+            let optional_features = Features::POLYGON_MODE_LINE
+                | Features::POLYGON_MODE_POINT
+                | Features::TEXTURE_BINDING_ARRAY
+                | Features::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING
+                | Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES;
 
             let adapter_limits = adapter.limits();
 
@@ -84,45 +125,38 @@ impl Internal {
         config.view_formats.push(surface_view_format);
         surface.configure(&device, &config);
 
-        let default_shader = load_shader(
+        // a single white pixel, so untextured geometry can go through the textured shader.
+        let default_texture = new_wgpu_texture(
             &device,
-            include_str!("../../shaders/fullscreen_triangle.wgsl"),
-        )?;
-
-        let default_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: None,
-            layout: Some(&default_shader.pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &default_shader.module,
-                entry_point: "vert_main",
-                buffers: &[],
-            },
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                unclipped_depth: false,
-                polygon_mode: wgpu::PolygonMode::Fill,
-                conservative: false,
+            UVec2::ONE,
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+            false,
+            1,
+        );
+        queue.write_texture(
+            default_texture.as_image_copy(),
+            &[255; 4],
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4),
+                rows_per_image: None,
             },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
+            wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
             },
-            fragment: Some(wgpu::FragmentState {
-                module: &default_shader.module,
-                entry_point: "frag_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::all(),
-                })],
-            }),
-            multiview: None,
-        });
+        );
+        let default_texture = default_texture.create_view(&Default::default());
+        let default_sampler = device.create_sampler(&Default::default());
+
+        let vertex_buffer = StreamBuffer::new(&device, BufferUsages::VERTEX, 0);
+        let index_buffer = StreamBuffer::new(&device, BufferUsages::INDEX, 0);
+        let uniform_buffer = StreamBuffer::new(
+            &device,
+            BufferUsages::UNIFORM,
+            device.limits().min_uniform_buffer_offset_alignment as u64,
+        );
 
         Ok(Self {
             device,
@@ -130,83 +164,750 @@ impl Internal {
             queue,
             surface,
             config,
-            default_shader,
-            default_pipeline,
+            sample_count: 1,
+            multisampled_swapchain: None,
+            swapchain_depth_format: None,
+            swapchain_depth: None,
+            default_texture,
+            default_sampler,
+            pass: Self::frame_pass(),
+            passes: vec![],
+            stats: FrameStats::default(),
+            vertex_buffer,
+            index_buffer,
+            uniform_buffer,
+            last_shader_poll: Instant::now(),
+            main_shader_path: None,
         })
     }
 
-    pub fn render(&mut self, data: &InternalData, window_size: UVec2) -> Result<()> {
+    /// The device, for creating resources in [`InternalData`].
+    pub fn device(&self) -> &Device {
+        &self.device
+    }
+
+    /// The queue, for uploading to resources in [`InternalData`].
+    pub fn queue(&self) -> &Queue {
+        &self.queue
+    }
+
+    pub fn render_size(&self) -> UVec2 {
+        UVec2::new(self.config.width, self.config.height)
+    }
+
+    /// Reads the main shader from `path` rather than the copy built into the library, so it's
+    /// hot reloaded like other shader files. Takes effect the next time it's loaded.
+    pub fn set_main_shader_path(&mut self, path: Option<impl Into<PathBuf>>) {
+        self.main_shader_path = path.map(Into::into);
+    }
+
+    /// Sets how many samples the swapchain is rendered with, 1 turning multisampling off.
+    pub fn set_sample_count(&mut self, sample_count: u32) -> Result<()> {
+        self.check_swapchain_formats(sample_count, self.swapchain_depth_format)?;
+        self.sample_count = sample_count;
+        Ok(())
+    }
+
+    /// Creates a color attachment for a [`FrameBuffer`], which is resolved into a texture of
+    /// its own at the end of every pass when `sample_count` is more than 1.
+    pub fn color_attachment(
+        &self,
+        size: UVec2,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Result<RenderAttachment> {
+        check_sample_count(&self.adapter, &self.device, format, sample_count)?;
+        RenderAttachment::color(&self.device, size, format, sample_count)
+    }
+
+    /// Creates a depth attachment for a [`FrameBuffer`].
+    pub fn depth_attachment(
+        &self,
+        size: UVec2,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Result<RenderAttachment> {
+        check_sample_count(&self.adapter, &self.device, format, sample_count)?;
+        RenderAttachment::depth(&self.device, size, format, sample_count)
+    }
+
+    /// Gives the swapchain a depth attachment of `format`, or takes it away with `None`.
+    pub fn set_swapchain_depth(&mut self, format: Option<wgpu::TextureFormat>) -> Result<()> {
+        if let Some(format) = format {
+            ensure!(
+                format.is_depth_stencil_format(),
+                "{format:?} can't be a depth attachment"
+            );
+        }
+        self.check_swapchain_formats(self.sample_count, format)?;
+        self.swapchain_depth_format = format;
+        self.swapchain_depth = None;
+        Ok(())
+    }
+
+    /// Checks that the swapchain and its depth attachment can both have `sample_count` samples.
+    fn check_swapchain_formats(
+        &self,
+        sample_count: u32,
+        depth_format: Option<wgpu::TextureFormat>,
+    ) -> Result<()> {
+        for format in std::iter::once(self.config.format).chain(depth_format) {
+            check_sample_count(&self.adapter, &self.device, format, sample_count)?;
+        }
+        Ok(())
+    }
+
+    /// The pass that draws go into, until another one is started.
+    pub fn pass(&mut self) -> &mut RenderPassBuilder {
+        &mut self.pass
+    }
+
+    /// Queues the current pass and starts recording into `pass`.
+    pub fn begin_pass(&mut self, pass: RenderPassBuilder) {
+        let current = std::mem::replace(&mut self.pass, pass);
+        self.passes.push(current.build());
+    }
+
+    /// Queues the current pass followed by an already built `pass`.
+    pub fn submit(&mut self, pass: RenderPass) {
+        self.begin_pass(RenderPassBuilder::default());
+        self.passes.push(pass);
+    }
+
+    /// Counters for the last rendered frame.
+    pub fn stats(&self) -> FrameStats {
+        self.stats
+    }
+
+    /// The first pass of every frame, which clears the swapchain and its depth attachment, if
+    /// it has one.
+    fn frame_pass() -> RenderPassBuilder {
+        RenderPassBuilder::default()
+            .clear_color([196, 99, 246, 255])
+            .clear_depth(1.0)
+            .clear_stencil(0)
+    }
+
+    /// Runs every pass queued this frame in order, then presents the swapchain.
+    pub fn render(&mut self, data: &mut InternalData, window_size: UVec2) -> Result<()> {
         self.config.height = window_size.y;
         self.config.width = window_size.x;
         self.surface.configure(&self.device, &self.config);
         let output = self.surface.get_current_texture()?;
-        let view = output.texture.create_view(&Default::default());
+        let swapchain = self.swapchain_attachment(&output.texture, window_size);
+        let swapchain_depth = self.swapchain_depth_attachment(window_size);
 
-        let clear_color: Color = [196, 99, 246, 255].into();
+        if self.last_shader_poll.elapsed() >= Self::SHADER_POLL_INTERVAL {
+            self.last_shader_poll = Instant::now();
+            data.reload_shaders(&self.device);
+        }
 
-        let mut encoder = self.device.create_command_encoder(&Default::default());
-        // per pipeline
-        {
-            let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
-                label: None,
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(clear_color.into()),
-                        store: true,
-                    },
-                })],
-                depth_stencil_attachment: None,
-                timestamp_writes: None,
-                occlusion_query_set: None,
-            });
-
-            render_pass.set_pipeline(&self.default_pipeline);
-            render_pass.set_viewport(
-                0.0,
-                0.0,
-                window_size.x as f32,
-                window_size.y as f32,
-                0.0,
-                1.0,
-            );
+        let mut passes = std::mem::take(&mut self.passes);
+        passes.push(std::mem::replace(&mut self.pass, Self::frame_pass()).build());
+
+        self.stats = FrameStats::default();
+        self.begin_streaming(&passes);
 
-            render_pass.draw(0..3, 0..1);
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+        for pass in passes.iter().filter(|pass| !pass.is_empty()) {
+            self.encode_pass(
+                &mut encoder,
+                data,
+                pass,
+                &swapchain,
+                swapchain_depth.as_ref(),
+            )?;
         }
 
+        self.stats.bytes_uploaded = self.vertex_buffer.uploaded()
+            + self.index_buffer.uploaded()
+            + self.uniform_buffer.uploaded();
+
         self.queue.submit(std::iter::once(encoder.finish()));
         output.present();
 
+        // keep the allocation around for next frame.
+        passes.clear();
+        self.passes = passes;
+
         Ok(())
     }
-}
 
-impl Internal {
-    fn _render<S: Into<(u32, u32)>>(&mut self, data: (), window_size: S) -> Result<()> {
-        /*  for each entry of pass data:
+    /// The attachment the swapchain pass draws into, multisampled into a texture of its own that
+    /// is resolved into `surface` if the sample count asks for it.
+    fn swapchain_attachment(&mut self, surface: &wgpu::Texture, size: UVec2) -> RenderAttachment {
+        let view = surface.create_view(&Default::default());
+        if self.sample_count == 1 {
+            self.multisampled_swapchain = None;
+            return RenderAttachment::new(view, self.config.format, size);
+        }
 
-            we set the viewport
-            render_pass.set_viewport(0, 0, window_size.x, window_size.y, 0., 1.);
+        let multisampled = match self.multisampled_swapchain.take() {
+            Some(texture)
+                if texture.sample_count() == self.sample_count
+                    && (texture.width(), texture.height()) == (size.x, size.y) =>
+            {
+                texture
+            }
+            _ => new_wgpu_texture(
+                &self.device,
+                size,
+                self.config.format,
+                true,
+                self.sample_count,
+            ),
+        };
+        let attachment = RenderAttachment::multisampled(
+            multisampled.create_view(&Default::default()),
+            view,
+            self.config.format,
+            size,
+            self.sample_count,
+        );
+        self.multisampled_swapchain = Some(multisampled);
+        attachment
+    }
 
-            we get the clear colour
-            let clear_colour = wgpu::LoadOp::Clear(
-                clear_color.into()
+    /// The depth attachment of the swapchain pass, if it has one.
+    fn swapchain_depth_attachment(&mut self, size: UVec2) -> Option<RenderAttachment> {
+        let format = self.swapchain_depth_format?;
+        let texture = match self.swapchain_depth.take() {
+            Some(texture)
+                if texture.sample_count() == self.sample_count
+                    && (texture.width(), texture.height()) == (size.x, size.y) =>
+            {
+                texture
+            }
+            _ => new_wgpu_texture(&self.device, size, format, true, self.sample_count),
+        };
+        let attachment = RenderAttachment::new_depth(
+            texture.create_view(&Default::default()),
+            format,
+            size,
+            self.sample_count,
+        );
+        self.swapchain_depth = Some(texture);
+        Some(attachment)
+    }
+
+    /// Makes room in the stream buffers for everything the frame will upload, so they are only
+    /// ever reallocated up front.
+    fn begin_streaming(&mut self, passes: &[RenderPass]) {
+        let (mut vertex_bytes, mut index_bytes, mut uniform_bytes) = (0, 0, 0);
+        for batch in passes.iter().flat_map(|pass| &pass.data) {
+            vertex_bytes += self
+                .vertex_buffer
+                .aligned(std::mem::size_of_val(batch.vertices.as_slice()) as u64);
+            index_bytes += self
+                .index_buffer
+                .aligned(std::mem::size_of_val(batch.indices.as_slice()) as u64);
+            uniform_bytes += self
+                .uniform_buffer
+                .aligned(std::mem::size_of::<Mat4>() as u64);
+        }
+
+        self.stats.buffer_reallocations = [
+            self.vertex_buffer.begin_frame(&self.device, vertex_bytes),
+            self.index_buffer.begin_frame(&self.device, index_bytes),
+            self.uniform_buffer.begin_frame(&self.device, uniform_bytes),
+        ]
+        .into_iter()
+        .filter(|&grown| grown)
+        .count();
+    }
+
+    fn encode_pass(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        data: &mut InternalData,
+        pass: &RenderPass,
+        swapchain: &RenderAttachment,
+        swapchain_depth: Option<&RenderAttachment>,
+    ) -> Result<()> {
+        let ops = Operations {
+            load: pass
+                .clear_color
+                .map_or(LoadOp::Load, |color| LoadOp::Clear(color.into())),
+            store: true,
+        };
+
+        let (target, formats, color_attachments, depth_stencil) = match &pass.target {
+            RenderTarget::Swapchain => (
+                swapchain,
+                TargetFormats {
+                    color: vec![Some(swapchain.format)],
+                    depth_stencil: swapchain_depth.map(|depth| depth.format),
+                    sample_count: swapchain.sample_count,
+                },
+                vec![Some(swapchain.color_attachment(ops))],
+                swapchain_depth,
+            ),
+            RenderTarget::FrameBuffer(framebuffer) => {
+                let formats = framebuffer.formats()?;
+                // every attachment has the same size, which is all the target is needed for.
+                let target = framebuffer
+                    .color_attachments
+                    .iter()
+                    .chain(std::iter::once(&framebuffer.depth_stencil_attachment))
+                    .flatten()
+                    .next()
+                    .ok_or_else(|| anyhow!("FrameBuffer has no attachments"))?;
+                let mut color_attachments = framebuffer
+                    .color_attachments([ops; FrameBuffer::MAXCOLORATTACHMENTS])
+                    .to_vec();
+                // trailing empty slots would make the pass incompatible with the pipeline.
+                color_attachments.truncate(formats.color.len());
+                (
+                    target,
+                    formats,
+                    color_attachments,
+                    framebuffer.depth_stencil_attachment.as_ref(),
+                )
+            }
+        };
+
+        // everything the batches need has to outlive the render pass.
+        let mut batches = Vec::with_capacity(pass.data.len());
+        for batch in &pass.data {
+            if let RenderTarget::FrameBuffer(framebuffer) = &pass.target {
+                ensure!(
+                    !batch.resources.values().any(|resource| matches!(
+                        resource,
+                        Resource::Attachment { framebuffer: sampled, .. } if Rc::ptr_eq(sampled, framebuffer)
+                    )),
+                    "A pass can't sample the framebuffer it draws into"
+                );
+            }
+            self.stats.vertices += batch.vertex_count;
+            self.stats.indices += batch.indices_count;
+            let shader = match batch.shader {
+                Some(shader) => shader,
+                None => self.main_shader(data)?,
+            };
+            batches.push(self.prepare_batch(data, target, &formats, shader, batch)?);
+        }
+
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: None,
+            color_attachments: &color_attachments,
+            depth_stencil_attachment: depth_stencil
+                .map(|depth| depth.depth_stencil_attachment(pass.depth_ops, pass.stencil_ops)),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        render_pass.set_viewport(
+            0.0,
+            0.0,
+            target.size.x as f32,
+            target.size.y as f32,
+            0.0,
+            1.0,
+        );
+
+        self.stats.passes += 1;
+        self.stats.batches += batches.len();
+
+        for batch in &batches {
+            let (x, y, width, height) = batch.scissor;
+            if width == 0 || height == 0 {
+                continue;
+            }
+            render_pass.set_scissor_rect(x, y, width, height);
+            render_pass.set_pipeline(&batch.pipeline);
+            render_pass.set_stencil_reference(batch.stencil_reference);
+            for (index, (bind_group, dynamic_offsets)) in batch.bind_groups.iter().enumerate() {
+                render_pass.set_bind_group(index as u32, bind_group, dynamic_offsets);
+            }
+            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(batch.vertices.clone()));
+            render_pass.set_index_buffer(
+                self.index_buffer.slice(batch.indices.clone()),
+                wgpu::IndexFormat::Uint32,
             );
+            render_pass.draw_indexed(0..batch.index_count, 0, 0..1);
+            self.stats.draw_calls += 1;
+        }
 
-            we get or create a new render_pipeline from the cache using the shader and other variables
+        Ok(())
+    }
 
-            we set all the uniform buffer
+    /// Loads `main.wgsl` the first time it's needed.
+    fn main_shader(&self, data: &mut InternalData) -> Result<Key<Shader>> {
+        if let Ok(key) = data.get_shader(Self::MAIN_SHADER) {
+            return Ok(key);
+        }
 
-            set the scissor rect
-            render_pass.set_scissor(scissor_rect);
+        if let Some(path) = self.main_shader_path.as_ref().filter(|path| path.exists()) {
+            match data.load_shader_file(&self.device, Self::MAIN_SHADER, path.clone()) {
+                Ok(key) => return Ok(key),
+                Err(e) => log::warn!("using the built in main shader: {e:#}"),
+            }
+        }
 
-            set vertex buffer
+        data.create_shader(
+            &self.device,
+            Self::MAIN_SHADER,
+            include_str!("../../shaders/main.wgsl"),
+        )
+    }
 
+    /// Gets the pipeline for the batch and uploads its geometry.
+    fn prepare_batch(
+        &mut self,
+        data: &mut InternalData,
+        target: &RenderAttachment,
+        formats: &TargetFormats,
+        key: Key<Shader>,
+        batch: &RenderPassData,
+    ) -> Result<PreparedBatch> {
+        let pipeline = data.get_pipeline(
+            &self.device,
+            formats,
+            key,
+            PrimitiveState {
+                topology: batch.topology.into(),
+                ..Default::default()
+            },
+            &batch.entry_points,
+            &batch.state,
+        )?;
 
+        let shader = data
+            .shader(key)
+            .ok_or_else(|| anyhow!("Could not find shader with key '{:?}'", key))?;
+
+        // map pixels to clip space, with the origin in the top left.
+        let size = target.size.as_vec2();
+        let matrix = Mat4::orthographic_rh(0., size.x, size.y, 0., -1., 1.) * batch.matrix;
+
+        let matrix = self
+            .uniform_buffer
+            .write(&self.queue, bytemuck::bytes_of(&matrix))?;
+
+        // only what the shader uses is bound.
+        let bind_groups = (0..shader.reflection.bind_group_count())
+            .map(|group| self.bind_group(data, shader, group, &batch.resources, &matrix))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(PreparedBatch {
+            pipeline,
+            bind_groups,
+            vertices: self
+                .vertex_buffer
+                .write(&self.queue, bytemuck::cast_slice(&batch.vertices))?,
+            indices: self
+                .index_buffer
+                .write(&self.queue, bytemuck::cast_slice(&batch.indices))?,
+            index_count: batch.indices_count as u32,
+            stencil_reference: batch
+                .state
+                .depth_stencil
+                .as_ref()
+                .map_or(0, |depth_stencil| depth_stencil.stencil_reference),
+            scissor: batch
+                .scissor
+                .map_or((0, 0, target.size.x, target.size.y), |rect| {
+                    clamp_scissor(rect, target.size)
+                }),
+        })
+    }
+}
 
-        */
+impl Internal {
+    /// Creates bind group `group` of `shader` from the resources set for the batch, and the
+    /// engine's own bindings where nothing is set, along with its dynamic offsets.
+    fn bind_group(
+        &self,
+        data: &InternalData,
+        shader: &Shader,
+        group: usize,
+        resources: &BTreeMap<(u32, u32), Resource>,
+        matrix: &Range<BufferAddress>,
+    ) -> Result<(wgpu::BindGroup, Vec<u32>)> {
+        let bindings = &shader.reflection.bind_groups[group];
+        let resource =
+            |binding: &purs::Binding| resources.get(&(group as u32, binding.entry.binding));
+        let context = |binding: &purs::Binding| {
+            format!(
+                "Could not bind @group({group}) @binding({}) {}",
+                binding.entry.binding,
+                binding.name.as_deref().unwrap_or("_")
+            )
+        };
+
+        // the bind group borrows the views, so they're created first.
+        let views = bindings
+            .iter()
+            .map(|binding| {
+                match resource(binding) {
+                    Some(Resource::Texture(key)) => {
+                        texture_views(data, std::slice::from_ref(key), None, &binding.entry)
+                    }
+                    Some(Resource::Textures(keys)) => {
+                        texture_views(data, keys, Some(keys.len()), &binding.entry)
+                    }
+                    _ => Ok(vec![]),
+                }
+                .with_context(|| context(binding))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let view_arrays: Vec<Vec<&TextureView>> =
+            views.iter().map(|views| views.iter().collect()).collect();
+
+        let mut dynamic_offsets = vec![];
+        let entries = bindings
+            .iter()
+            .zip(views.iter().zip(&view_arrays))
+            .map(|(binding, (views, view_array))| {
+                let entry = &binding.entry;
+                let mut resource = match resource(binding) {
+                    Some(Resource::Texture(_)) => BindingResource::TextureView(&views[0]),
+                    Some(Resource::Textures(_)) => BindingResource::TextureViewArray(view_array),
+                    Some(Resource::Attachment {
+                        framebuffer,
+                        attachment,
+                    }) => BindingResource::TextureView(
+                        attachment_view(framebuffer, *attachment, entry)
+                            .with_context(|| context(binding))?,
+                    ),
+                    Some(&Resource::Sampler(key)) => {
+                        sampler(data, key, entry).with_context(|| context(binding))?
+                    }
+                    Some(&Resource::Buffer {
+                        buffer,
+                        offset,
+                        size,
+                    }) => self
+                        .buffer_binding(data, entry, buffer, offset, size)
+                        .with_context(|| context(binding))?,
+                    None if purs::builtin_binding(group, entry) => match (group, entry.binding) {
+                        (0, 0) => BindingResource::TextureView(&self.default_texture),
+                        (0, 1) => BindingResource::Sampler(&self.default_sampler),
+                        _ => BindingResource::Buffer(BufferBinding {
+                            buffer: self.uniform_buffer.buffer(),
+                            offset: matrix.start,
+                            size: BufferSize::new(matrix.end - matrix.start),
+                        }),
+                    },
+                    None => bail!(
+                        "{}: the engine doesn't bind a {} there, so it has to be set with `RenderPassBuilder::set_resource`",
+                        context(binding),
+                        purs::binding_type(&entry.ty)
+                    ),
+                };
+                // the offset is passed when the bind group is set instead.
+                if let (
+                    BindingResource::Buffer(buffer),
+                    wgpu::BindingType::Buffer {
+                        has_dynamic_offset: true,
+                        ..
+                    },
+                ) = (&mut resource, entry.ty)
+                {
+                    let offset = u32::try_from(buffer.offset).with_context(|| {
+                        format!("{}: dynamic offsets have to fit in 32 bits", context(binding))
+                    })?;
+                    dynamic_offsets.push((entry.binding, offset));
+                    buffer.offset = 0;
+                }
+                Ok(BindGroupEntry {
+                    binding: entry.binding,
+                    resource,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let bind_group = self.device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout: &shader.bind_group_layouts[group],
+            entries: &entries,
+        });
+        // they go in the order of their bindings.
+        dynamic_offsets.sort_unstable_by_key(|&(binding, _)| binding);
+        Ok((
+            bind_group,
+            dynamic_offsets
+                .into_iter()
+                .map(|(_, offset)| offset)
+                .collect(),
+        ))
+    }
 
-        Ok(())
+    /// The part of a buffer set for a batch, checked against the binding it goes to.
+    fn buffer_binding<'a>(
+        &self,
+        data: &'a InternalData,
+        entry: &wgpu::BindGroupLayoutEntry,
+        key: Key<wgpu::Buffer>,
+        offset: BufferAddress,
+        size: Option<BufferSize>,
+    ) -> Result<BindingResource<'a>> {
+        let buffer = data
+            .buffer(key)
+            .ok_or_else(|| anyhow!("Could not find buffer with key '{:?}'", key))?;
+        let wgpu::BindingType::Buffer {
+            ty,
+            min_binding_size,
+            ..
+        } = entry.ty
+        else {
+            bail!(
+                "a buffer can't be bound to a {}",
+                purs::binding_type(&entry.ty)
+            );
+        };
+        ensure!(entry.count.is_none(), "it's a binding array");
+
+        let limits = self.device.limits();
+        let (usage, alignment) = match ty {
+            wgpu::BufferBindingType::Uniform => (
+                BufferUsages::UNIFORM,
+                limits.min_uniform_buffer_offset_alignment,
+            ),
+            wgpu::BufferBindingType::Storage { .. } => (
+                BufferUsages::STORAGE,
+                limits.min_storage_buffer_offset_alignment,
+            ),
+        };
+        ensure!(
+            buffer.usage().contains(usage),
+            "the buffer wasn't created with {usage:?} usage"
+        );
+        ensure!(
+            offset.is_multiple_of(alignment as u64),
+            "offset {offset} isn't a multiple of {alignment}"
+        );
+        let bound = size.map_or(buffer.size().saturating_sub(offset), |size| size.get());
+        ensure!(
+            offset
+                .checked_add(bound)
+                .is_some_and(|end| end <= buffer.size()),
+            "{bound} bytes from offset {offset} don't fit in a buffer of {} bytes",
+            buffer.size()
+        );
+        let needed = min_binding_size.map_or(1, |size| size.get());
+        ensure!(
+            bound >= needed,
+            "the shader reads {needed} bytes, but only {bound} are bound"
+        );
+
+        // sized explicitly, as it's needed if the offset turns out to be dynamic.
+        Ok(BindingResource::Buffer(BufferBinding {
+            buffer,
+            offset,
+            size: BufferSize::new(bound),
+        }))
     }
 }
+
+/// Views of the textures set for a batch, checked against the binding they go to, which is a
+/// `binding_array` of `length` for arrays of textures.
+fn texture_views(
+    data: &InternalData,
+    keys: &[Key<Texture>],
+    length: Option<usize>,
+    entry: &wgpu::BindGroupLayoutEntry,
+) -> Result<Vec<TextureView>> {
+    // binding arrays can't be empty.
+    ensure!(!keys.is_empty(), "no textures are set");
+    keys.iter()
+        .map(|&key| {
+            let texture = data
+                .texture(key)
+                .ok_or_else(|| anyhow!("Could not find texture with key '{:?}'", key))?;
+            check_texture_binding(entry, texture.format, false, length)?;
+            Ok(texture.get_view())
+        })
+        .collect()
+}
+
+/// The view of a framebuffer attachment set for a batch, checked against the binding it goes
+/// to.
+fn attachment_view<'a>(
+    framebuffer: &'a FrameBuffer,
+    attachment: Attachment,
+    entry: &wgpu::BindGroupLayoutEntry,
+) -> Result<&'a TextureView> {
+    let attachment = framebuffer
+        .attachment(attachment)
+        .ok_or_else(|| anyhow!("the framebuffer has no {attachment:?} attachment"))?;
+    let (view, multisampled) = attachment.sampled_view();
+    check_texture_binding(entry, attachment.format, multisampled, None)?;
+    Ok(view)
+}
+
+/// Checks that a 2D texture of `format` can be bound to `entry`, which is a `binding_array` of
+/// `length` for arrays of textures.
+fn check_texture_binding(
+    entry: &wgpu::BindGroupLayoutEntry,
+    format: wgpu::TextureFormat,
+    multisampled: bool,
+    length: Option<usize>,
+) -> Result<()> {
+    let wgpu::BindingType::Texture {
+        sample_type,
+        view_dimension,
+        multisampled: expected_multisampled,
+    } = entry.ty
+    else {
+        bail!(
+            "a texture can't be bound to a {}",
+            purs::binding_type(&entry.ty)
+        );
+    };
+    match (entry.count, length) {
+        (None, None) => {}
+        (Some(count), Some(length)) => ensure!(
+            count.get() as usize == length,
+            "it's a binding array of {count} textures, but {length} are set"
+        ),
+        (Some(count), None) => bail!("it's a binding array of {count} textures, not a texture"),
+        (None, Some(_)) => bail!("it's a single texture, not a binding array"),
+    }
+    ensure!(
+        view_dimension == wgpu::TextureViewDimension::D2,
+        "textures are bound as 2D textures, not as a {}",
+        purs::binding_type(&entry.ty)
+    );
+    ensure!(
+        multisampled == expected_multisampled,
+        "a {} texture can't be bound to a {}",
+        if multisampled {
+            "multisampled"
+        } else {
+            "single sampled"
+        },
+        purs::binding_type(&entry.ty)
+    );
+    check_sample_type(format, sample_type)
+}
+
+/// A sampler set for a batch, checked against the binding it goes to.
+fn sampler<'a>(
+    data: &'a InternalData,
+    key: Key<Sampler>,
+    entry: &wgpu::BindGroupLayoutEntry,
+) -> Result<BindingResource<'a>> {
+    let sampler = data
+        .sampler(key)
+        .ok_or_else(|| anyhow!("Could not find sampler with key '{:?}'", key))?;
+    ensure!(
+        matches!(entry.ty, wgpu::BindingType::Sampler(_)) && entry.count.is_none(),
+        "a sampler can't be bound to a {}",
+        purs::binding_type(&entry.ty)
+    );
+    Ok(BindingResource::Sampler(sampler))
+}
+
+/// GPU resources for a batch, kept alive until the pass is encoded, along with where its
+/// geometry was written to in the stream buffers.
+struct PreparedBatch {
+    pipeline: Rc<wgpu::RenderPipeline>,
+    /// With the dynamic offsets to set them with.
+    bind_groups: Vec<(wgpu::BindGroup, Vec<u32>)>,
+    vertices: Range<BufferAddress>,
+    indices: Range<BufferAddress>,
+    index_count: u32,
+    stencil_reference: u32,
+    scissor: (u32, u32, u32, u32),
+}
@@ -1,28 +1,53 @@
 pub mod data;
 pub mod internal;
-mod renderpass;
+pub mod renderpass;
 pub mod types;
-use anyhow::Result;
 pub use data::InternalData;
-use glam::{UVec2, Vec2};
+use glam::Vec2;
 pub use internal::Internal;
-use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle};
 use types::Color;
 
+/// Shorthands for drawing into the current pass, see [`RenderPassBuilder`](renderpass::RenderPassBuilder).
 impl Internal {
-    fn render_size(&self) -> UVec2 {
-        todo!()
-    }
-    fn draw_rect<P: Into<Vec2>, S: Into<Vec2>, C: Into<Color>>(
-        &self,
+    pub fn draw_rect<P: Into<Vec2>, S: Into<Vec2>, C: Into<Color>>(
+        &mut self,
         position: P,
         size: S,
         color: C,
     ) {
-        let position = position.into();
-        let size = size.into();
-        let color = color.into();
-        // create vertices?
+        self.pass().draw_rect(position, size, color)
+    }
+
+    pub fn draw_line<P: Into<Vec2>, Q: Into<Vec2>, C: Into<Color>>(
+        &mut self,
+        start: P,
+        end: Q,
+        thickness: f32,
+        color: C,
+    ) {
+        self.pass().draw_line(start, end, thickness, color)
+    }
+
+    pub fn draw_triangle<A: Into<Vec2>, B: Into<Vec2>, D: Into<Vec2>, C: Into<Color>>(
+        &mut self,
+        a: A,
+        b: B,
+        c: D,
+        color: C,
+    ) {
+        self.pass().draw_triangle(a, b, c, color)
+    }
+
+    pub fn draw_polygon<P: Into<Vec2>, I: IntoIterator<Item = P>, C: Into<Color>>(
+        &mut self,
+        points: I,
+        color: C,
+    ) {
+        self.pass().draw_polygon(points, color)
+    }
+
+    pub fn draw_circle<P: Into<Vec2>, C: Into<Color>>(&mut self, center: P, radius: f32, color: C) {
+        self.pass().draw_circle(center, radius, color)
     }
 }
 
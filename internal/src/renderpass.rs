@@ -1,19 +1,106 @@
-use crate::types::{shader::Shader, vertex::Vertex, Color, Topology};
+use crate::types::{
+    framebuffer::{Attachment, FrameBuffer},
+    pipeline::{BlendMode, DepthStencil, EntryPoints, RenderState},
+    resource::Resource,
+    shader::Shader,
+    texture::Texture,
+    vertex::Vertex,
+    Color, Topology,
+};
 use arena::Key;
-use glam::{Mat3, Mat4};
+use glam::{Mat3, Mat4, UVec2, Vec2};
+use std::{collections::BTreeMap, rc::Rc};
+use wgpu::{LoadOp, Operations};
 
+/// Where a render pass draws to.
+#[derive(Debug, Default, Clone)]
+pub enum RenderTarget {
+    /// The window's surface texture for the current frame.
+    #[default]
+    Swapchain,
+    FrameBuffer(Rc<FrameBuffer>),
+}
+
+/// Resources set for a batch by `@group` and `@binding`, shared until one of them changes.
+pub(crate) type Resources = Rc<BTreeMap<(u32, u32), Resource>>;
+
+/// Counters for a single rendered frame.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FrameStats {
+    pub passes: usize,
+    pub batches: usize,
+    pub draw_calls: usize,
+    pub vertices: usize,
+    pub indices: usize,
+    /// Vertex, index and uniform data written to the GPU.
+    pub bytes_uploaded: u64,
+    /// How many stream buffers had to grow to fit the frame.
+    pub buffer_reallocations: usize,
+}
+
+/// A finished pass, ready to be submitted to [`Internal`](crate::Internal).
 #[derive(Debug)]
-struct RenderPass {
-    data: Vec<RenderPassData>,
+pub struct RenderPass {
+    pub(crate) clear_color: Option<Color>,
+    pub(crate) depth_ops: Operations<f32>,
+    pub(crate) stencil_ops: Operations<u32>,
+    pub(crate) target: RenderTarget,
+    pub(crate) data: Vec<RenderPassData>,
 }
 
-impl RenderPass {}
+impl RenderPass {
+    /// Whether running the pass would have no effect on its target.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.clear_color.is_none()
+            && self.depth_ops.load == LoadOp::Load
+            && self.stencil_ops.load == LoadOp::Load
+            && self.data.is_empty()
+    }
+}
 
-#[derive(Debug, Default)]
-struct RenderPassBuilder {
+/// Records the geometry for a single render pass.
+#[derive(Debug)]
+pub struct RenderPassBuilder {
     clear_color: Option<Color>,
-    target: Option<()>,
+    depth_ops: Operations<f32>,
+    stencil_ops: Operations<u32>,
+    target: RenderTarget,
+    /// Saved transforms, on top of the base one which is never popped.
     matrix_stack: Vec<Mat3>,
+    scissor_stack: Vec<(i32, i32, i32, i32)>,
+    shader: Option<Key<Shader>>,
+    entry_points: EntryPoints,
+    state: RenderState,
+    resources: Resources,
+    texture_index: u32,
+    view: Mat4,
+    data: Vec<RenderPassData>,
+}
+
+impl Default for RenderPassBuilder {
+    fn default() -> Self {
+        Self {
+            clear_color: None,
+            depth_ops: Operations {
+                load: LoadOp::Load,
+                store: true,
+            },
+            stencil_ops: Operations {
+                load: LoadOp::Load,
+                store: true,
+            },
+            target: Default::default(),
+            matrix_stack: vec![Mat3::IDENTITY],
+            scissor_stack: vec![],
+            shader: None,
+            entry_points: EntryPoints::default(),
+            state: RenderState::default(),
+            resources: Resources::default(),
+            texture_index: 0,
+            view: Mat4::IDENTITY,
+            data: vec![],
+        }
+    }
 }
 
 impl RenderPassBuilder {
@@ -22,26 +109,462 @@ impl RenderPassBuilder {
         self
     }
 
-    pub fn target(self, target: ()) -> Self {
-        todo!()
+    /// What happens to the depth of the target's depth attachment, if it has one.
+    pub fn depth_ops(mut self, ops: Operations<f32>) -> Self {
+        self.set_depth_ops(ops);
+        self
+    }
+
+    /// What happens to the stencil of the target's depth attachment, if it has one.
+    pub fn stencil_ops(mut self, ops: Operations<u32>) -> Self {
+        self.set_stencil_ops(ops);
+        self
+    }
+
+    pub fn clear_depth(mut self, depth: f32) -> Self {
+        self.set_clear_depth(depth);
+        self
+    }
+
+    pub fn clear_stencil(mut self, stencil: u32) -> Self {
+        self.set_clear_stencil(stencil);
+        self
+    }
+
+    pub fn set_depth_ops(&mut self, ops: Operations<f32>) {
+        self.depth_ops = ops;
+    }
+
+    pub fn set_stencil_ops(&mut self, ops: Operations<u32>) {
+        self.stencil_ops = ops;
+    }
+
+    pub fn set_clear_depth(&mut self, depth: f32) {
+        self.set_depth_ops(Operations {
+            load: LoadOp::Clear(depth),
+            store: true,
+        });
+    }
+
+    pub fn set_clear_stencil(&mut self, stencil: u32) {
+        self.set_stencil_ops(Operations {
+            load: LoadOp::Clear(stencil),
+            store: true,
+        });
+    }
+
+    pub fn target(mut self, target: RenderTarget) -> Self {
+        self.target = target;
+        self
     }
 
     pub fn build(self) -> RenderPass {
-        RenderPass { data: vec![] }
+        RenderPass {
+            clear_color: self.clear_color,
+            depth_ops: self.depth_ops,
+            stencil_ops: self.stencil_ops,
+            target: self.target,
+            data: self
+                .data
+                .into_iter()
+                .filter(|data| !data.is_empty())
+                .collect(),
+        }
+    }
+
+    /// Shader used for everything drawn after this, `None` being the default `main.wgsl`.
+    ///
+    /// Shaders get the same bindings as `main.wgsl` where nothing else is set with
+    /// [`set_resource`](Self::set_resource): the texture at `@group(0) @binding(0)`, a
+    /// sampler at `@group(0) @binding(1)` and the transform matrix at `@group(1) @binding(0)`.
+    /// Drawing with a shader that has any other binding left unset fails.
+    pub fn set_shader(&mut self, shader: Option<Key<Shader>>) {
+        self.shader = shader;
+    }
+
+    /// Entry points of the shader used for everything drawn after this.
+    pub fn set_entry_points(&mut self, entry_points: EntryPoints) {
+        self.entry_points = entry_points;
+    }
+
+    /// How everything drawn after this is blended with the target.
+    pub fn set_blend_mode(&mut self, blend: BlendMode) {
+        self.state.blend = blend;
+    }
+
+    /// Depth and stencil tests for everything drawn after this, which needs a target with a
+    /// depth attachment. `None` leaves the depth untouched.
+    pub fn set_depth_stencil(&mut self, depth_stencil: Option<DepthStencil>) {
+        self.state.depth_stencil = depth_stencil;
+    }
+
+    /// Texture bound for everything drawn after this, `None` being plain white.
+    pub fn set_texture(&mut self, texture: Option<Key<Texture>>) {
+        self.set_resource(0, 0, texture.map(Resource::Texture));
+    }
+
+    /// Binds what an earlier pass drew into `framebuffer`'s `attachment` as the texture for
+    /// everything drawn after this, like [`set_texture`](Self::set_texture).
+    pub fn set_framebuffer_texture(
+        &mut self,
+        framebuffer: Rc<FrameBuffer>,
+        attachment: Attachment,
+    ) {
+        self.set_resource(
+            0,
+            0,
+            Some(Resource::Attachment {
+                framebuffer,
+                attachment,
+            }),
+        );
+    }
+
+    /// Binds `resource` to `@group(group) @binding(binding)` for everything drawn after this,
+    /// in place of what the engine binds there. `None` unsets it.
+    pub fn set_resource(&mut self, group: u32, binding: u32, resource: Option<Resource>) {
+        if self.resources.get(&(group, binding)) == resource.as_ref() {
+            return;
+        }
+        // batches keep the resources they were started with.
+        let resources = Rc::make_mut(&mut self.resources);
+        match resource {
+            Some(resource) => resources.insert((group, binding), resource),
+            None => resources.remove(&(group, binding)),
+        };
+    }
+
+    /// Which texture of a [`Resource::Textures`] array everything drawn after this reads, given
+    /// to the shader as a `u32` vertex input at `@location(3)`. Unlike the resources, changing
+    /// it doesn't start a new batch, so sprites from many textures can be drawn at once.
+    pub fn set_texture_index(&mut self, index: u32) {
+        self.texture_index = index;
+    }
+
+    /// Matrix applied on the GPU to everything drawn after this, before the projection to the
+    /// target. Unlike [`transform`](Self::transform) changing it starts a new batch.
+    pub fn set_view(&mut self, view: Mat4) {
+        self.view = view;
+    }
+
+    /// The batch new geometry gets appended to, a new one is only started when some state that
+    /// needs a separate draw call has changed since the last one.
+    fn batch(&mut self, topology: Topology) -> &mut RenderPassData {
+        let scissor = self.scissor();
+        if self
+            .data
+            .last()
+            .is_some_and(|batch| self.continues(batch, topology, scissor))
+        {
+            return self.data.last_mut().unwrap();
+        }
+
+        let mut state = RenderPassData::new(self.shader, self.view, topology);
+        state.entry_points = self.entry_points.clone();
+        state.state = self.state.clone();
+        state.resources = self.resources.clone();
+        state.scissor = scissor;
+
+        match self.data.last_mut() {
+            Some(batch) if batch.is_empty() => *batch = state,
+            _ => self.data.push(state),
+        }
+        self.data.last_mut().unwrap()
+    }
+
+    /// Whether geometry drawn now could go in the same draw call as `batch`.
+    fn continues(
+        &self,
+        batch: &RenderPassData,
+        topology: Topology,
+        scissor: Option<(i32, i32, i32, i32)>,
+    ) -> bool {
+        batch.shader == self.shader
+            && batch.entry_points == self.entry_points
+            && batch.state == self.state
+            && (Rc::ptr_eq(&batch.resources, &self.resources) || batch.resources == self.resources)
+            && batch.matrix == self.view
+            && batch.topology == topology
+            && batch.scissor == scissor
+    }
+
+    /// Appends geometry to the current batch, transformed by the current matrix.
+    fn push(&mut self, topology: Topology, vertices: &[Vertex], indices: &[u32]) {
+        let matrix = self.matrix();
+        let texture_index = self.texture_index;
+        if matrix == Mat3::IDENTITY && texture_index == 0 {
+            self.batch(topology).push(vertices, indices);
+        } else {
+            let vertices: Vec<Vertex> = vertices
+                .iter()
+                .map(|vertex| {
+                    vertex
+                        .transformed(&matrix)
+                        .with_texture_index(texture_index)
+                })
+                .collect();
+            self.batch(topology).push(&vertices, indices);
+        }
+    }
+
+    /// The transform currently applied to everything drawn.
+    pub fn matrix(&self) -> Mat3 {
+        *self.matrix_stack.last().unwrap()
+    }
+
+    /// Saves the current transform, to be restored by [`pop_matrix`](Self::pop_matrix).
+    pub fn push_matrix(&mut self) {
+        self.matrix_stack.push(self.matrix());
+    }
+
+    /// Restores the transform saved by the last [`push_matrix`](Self::push_matrix).
+    pub fn pop_matrix(&mut self) {
+        debug_assert!(
+            self.matrix_stack.len() > 1,
+            "pop_matrix without a matching push_matrix"
+        );
+        if self.matrix_stack.len() > 1 {
+            self.matrix_stack.pop();
+        }
+    }
+
+    /// Applies `matrix` on top of the current transform.
+    pub fn transform(&mut self, matrix: Mat3) {
+        *self.matrix_stack.last_mut().unwrap() *= matrix;
+    }
+
+    pub fn translate(&mut self, offset: impl Into<Vec2>) {
+        self.transform(Mat3::from_translation(offset.into()))
+    }
+
+    /// Rotates clockwise by `angle` radians, as the y axis points down.
+    pub fn rotate(&mut self, angle: f32) {
+        self.transform(Mat3::from_angle(angle))
     }
+
+    pub fn scale(&mut self, scale: impl Into<Vec2>) {
+        self.transform(Mat3::from_scale(scale.into()))
+    }
+
+    /// The rectangle everything drawn is clipped to, if any.
+    pub fn scissor(&self) -> Option<(i32, i32, i32, i32)> {
+        self.scissor_stack.last().copied()
+    }
+
+    /// Clips everything drawn to the given rectangle, in target pixels, until the matching
+    /// [`pop_scissor`](Self::pop_scissor). Nested rectangles are intersected with the current one.
+    pub fn push_scissor(&mut self, x: i32, y: i32, width: i32, height: i32) {
+        let rect = (x, y, width.max(0), height.max(0));
+        let rect = match self.scissor() {
+            Some(current) => intersect_rects(current, rect),
+            None => rect,
+        };
+        self.scissor_stack.push(rect);
+    }
+
+    pub fn pop_scissor(&mut self) {
+        self.scissor_stack.pop();
+    }
+
+    /// Runs `f` with its own copy of the current transform, which is dropped afterwards.
+    pub fn with_transform<R>(&mut self, f: impl FnOnce(&mut Self) -> R) -> R {
+        self.push_matrix();
+        let result = f(self);
+        self.pop_matrix();
+        result
+    }
+
+    /// Draws a filled rectangle with its top left corner at `position`.
+    pub fn draw_rect<P: Into<Vec2>, S: Into<Vec2>, C: Into<Color>>(
+        &mut self,
+        position: P,
+        size: S,
+        color: C,
+    ) {
+        let position = position.into();
+        let size = size.into();
+        let color = color.into();
+
+        self.push(
+            Topology::Triangles,
+            &[
+                Vertex::new(position, Vec2::new(0., 0.), color),
+                Vertex::new(position + Vec2::new(size.x, 0.), Vec2::new(1., 0.), color),
+                Vertex::new(position + size, Vec2::new(1., 1.), color),
+                Vertex::new(position + Vec2::new(0., size.y), Vec2::new(0., 1.), color),
+            ],
+            &[0, 1, 2, 0, 2, 3],
+        );
+    }
+
+    /// Draws a line `thickness` pixels wide between `start` and `end`.
+    pub fn draw_line<P: Into<Vec2>, Q: Into<Vec2>, C: Into<Color>>(
+        &mut self,
+        start: P,
+        end: Q,
+        thickness: f32,
+        color: C,
+    ) {
+        let start = start.into();
+        let end = end.into();
+        let color = color.into();
+
+        // offset both ends along the line's normal to get a quad.
+        let normal = (end - start).perp().normalize_or_zero() * thickness * 0.5;
+
+        self.push(
+            Topology::Triangles,
+            &[
+                Vertex::new(start + normal, Vec2::ZERO, color),
+                Vertex::new(end + normal, Vec2::ZERO, color),
+                Vertex::new(end - normal, Vec2::ZERO, color),
+                Vertex::new(start - normal, Vec2::ZERO, color),
+            ],
+            &[0, 1, 2, 0, 2, 3],
+        );
+    }
+
+    /// Draws a filled triangle.
+    pub fn draw_triangle<A: Into<Vec2>, B: Into<Vec2>, D: Into<Vec2>, C: Into<Color>>(
+        &mut self,
+        a: A,
+        b: B,
+        c: D,
+        color: C,
+    ) {
+        let color = color.into();
+
+        self.push(
+            Topology::Triangles,
+            &[
+                Vertex::new(a.into(), Vec2::ZERO, color),
+                Vertex::new(b.into(), Vec2::ZERO, color),
+                Vertex::new(c.into(), Vec2::ZERO, color),
+            ],
+            &[0, 1, 2],
+        );
+    }
+
+    /// Draws a filled convex polygon, anything with fewer than 3 points is ignored.
+    pub fn draw_polygon<P: Into<Vec2>, I: IntoIterator<Item = P>, C: Into<Color>>(
+        &mut self,
+        points: I,
+        color: C,
+    ) {
+        let color = color.into();
+        let vertices: Vec<Vertex> = points
+            .into_iter()
+            .map(|point| Vertex::new(point.into(), Vec2::ZERO, color))
+            .collect();
+
+        if vertices.len() < 3 {
+            return;
+        }
+
+        self.push(
+            Topology::Triangles,
+            &vertices,
+            &fan_indices(vertices.len() as u32 - 1),
+        );
+    }
+
+    /// Draws a filled circle, the number of segments is picked from the radius.
+    pub fn draw_circle<P: Into<Vec2>, C: Into<Color>>(&mut self, center: P, radius: f32, color: C) {
+        let center = center.into();
+        let color = color.into();
+        let segments = ((radius.abs().sqrt() * 4.).ceil() as u32).clamp(8, 128);
+
+        // center first, then the outer ring, with the texture mapped across the bounds.
+        let vertices: Vec<Vertex> = std::iter::once(Vertex::new(center, Vec2::splat(0.5), color))
+            .chain((0..segments).map(|segment| {
+                let direction =
+                    Vec2::from_angle(segment as f32 / segments as f32 * std::f32::consts::TAU);
+                Vertex::new(
+                    center + direction * radius,
+                    Vec2::splat(0.5) + direction * 0.5,
+                    color,
+                )
+            }))
+            .collect();
+
+        let mut indices = fan_indices(segments);
+        // close the ring.
+        indices.extend([0, segments, 1]);
+
+        self.push(Topology::Triangles, &vertices, &indices);
+    }
+
+    /// Draws a single pixel wide line, for debugging.
+    pub fn draw_hairline<P: Into<Vec2>, Q: Into<Vec2>, C: Into<Color>>(
+        &mut self,
+        start: P,
+        end: Q,
+        color: C,
+    ) {
+        let color = color.into();
+
+        self.push(
+            Topology::Lines,
+            &[
+                Vertex::new(start.into(), Vec2::ZERO, color),
+                Vertex::new(end.into(), Vec2::ZERO, color),
+            ],
+            &[0, 1],
+        );
+    }
+
+    /// Draws a single pixel, for debugging.
+    pub fn draw_point<P: Into<Vec2>, C: Into<Color>>(&mut self, position: P, color: C) {
+        self.push(
+            Topology::Points,
+            &[Vertex::new(position.into(), Vec2::ZERO, color.into())],
+            &[0],
+        );
+    }
+}
+
+fn intersect_rects(a: (i32, i32, i32, i32), b: (i32, i32, i32, i32)) -> (i32, i32, i32, i32) {
+    let x = a.0.max(b.0);
+    let y = a.1.max(b.1);
+    let right = a.0.saturating_add(a.2).min(b.0.saturating_add(b.2));
+    let bottom = a.1.saturating_add(a.3).min(b.1.saturating_add(b.3));
+    (
+        x,
+        y,
+        right.saturating_sub(x).max(0),
+        bottom.saturating_sub(y).max(0),
+    )
+}
+
+/// Clamps a scissor rectangle to a target of `size`, as `(x, y, width, height)`.
+pub(crate) fn clamp_scissor(rect: (i32, i32, i32, i32), size: UVec2) -> (u32, u32, u32, u32) {
+    let (width, height) = (size.x as i32, size.y as i32);
+    let x = rect.0.clamp(0, width);
+    let y = rect.1.clamp(0, height);
+    let right = rect.0.saturating_add(rect.2).clamp(x, width);
+    let bottom = rect.1.saturating_add(rect.3).clamp(y, height);
+    (x as u32, y as u32, (right - x) as u32, (bottom - y) as u32)
+}
+
+/// Triangle fan indices around vertex 0, over the `count` vertices that follow it.
+fn fan_indices(count: u32) -> Vec<u32> {
+    (1..count).flat_map(|n| [0, n, n + 1]).collect()
 }
 
 #[derive(Debug)]
-struct RenderPassData {
-    vertices: Vec<Vertex>,
-    indices: Vec<u32>,
-    vertex_count: usize,
-    indices_count: usize,
-    shader: Option<Key<Shader>>,
-    matrix: Mat4,
-    topology: Topology,
-    scissor: Option<(i32, i32, i32, i32)>,
-    clear_color: Color,
+pub(crate) struct RenderPassData {
+    pub(crate) vertices: Vec<Vertex>,
+    pub(crate) indices: Vec<u32>,
+    pub(crate) vertex_count: usize,
+    pub(crate) indices_count: usize,
+    pub(crate) shader: Option<Key<Shader>>,
+    pub(crate) entry_points: EntryPoints,
+    pub(crate) state: RenderState,
+    pub(crate) resources: Resources,
+    pub(crate) matrix: Mat4,
+    pub(crate) topology: Topology,
+    pub(crate) scissor: Option<(i32, i32, i32, i32)>,
+    pub(crate) clear_color: Color,
 }
 
 impl RenderPassData {
@@ -52,12 +575,29 @@ impl RenderPassData {
             vertex_count: 0,
             indices_count: 0,
             shader,
+            entry_points: EntryPoints::default(),
+            state: RenderState::default(),
+            resources: Resources::default(),
             matrix,
             topology,
             scissor: None,
             clear_color: Default::default(),
         }
     }
+
+    /// Appends geometry to the batch, offsetting the indices past the vertices already in it.
+    pub(crate) fn push(&mut self, vertices: &[Vertex], indices: &[u32]) {
+        let offset = self.vertices.len() as u32;
+        self.vertices.extend_from_slice(vertices);
+        self.indices
+            .extend(indices.iter().map(|index| index + offset));
+        self.vertex_count = self.vertices.len();
+        self.indices_count = self.indices.len();
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
 }
 
 impl Default for RenderPassData {
@@ -65,3 +605,212 @@ impl Default for RenderPassData {
         Self::new(None, Mat4::IDENTITY, Topology::Triangles)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn push_offsets_indices() {
+        let mut data = RenderPassData::default();
+        let vertex = Vertex::new(Vec2::ZERO, Vec2::ZERO, Color::WHITE);
+        data.push(&[vertex; 3], &[0, 1, 2]);
+        data.push(&[vertex; 4], &[0, 1, 2, 0, 2, 3]);
+
+        assert_eq!(data.vertex_count, 7);
+        assert_eq!(data.indices, &[0, 1, 2, 3, 4, 5, 3, 5, 6]);
+    }
+
+    #[test]
+    fn matrix_stack() {
+        let mut builder = RenderPassBuilder::default();
+        builder.translate((10., 0.));
+        builder.with_transform(|builder| {
+            builder.scale((2., 2.));
+            builder.draw_triangle((0., 0.), (1., 0.), (0., 1.), Color::WHITE);
+        });
+        builder.draw_triangle((0., 0.), (1., 0.), (0., 1.), Color::WHITE);
+
+        assert_eq!(builder.matrix(), Mat3::from_translation(Vec2::new(10., 0.)));
+
+        let pass = builder.build();
+        let positions: Vec<Vec2> = pass.data[0]
+            .vertices
+            .iter()
+            .map(|vertex| vertex.pos)
+            .collect();
+        assert_eq!(
+            positions,
+            &[
+                Vec2::new(10., 0.),
+                Vec2::new(12., 0.),
+                Vec2::new(10., 2.),
+                Vec2::new(10., 0.),
+                Vec2::new(11., 0.),
+                Vec2::new(10., 1.),
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "pop_matrix without a matching push_matrix")]
+    fn unbalanced_pop_matrix() {
+        let mut builder = RenderPassBuilder::default();
+        builder.translate((10., 0.));
+        builder.pop_matrix();
+    }
+
+    #[test]
+    fn scissor_splits_batches() {
+        let mut builder = RenderPassBuilder::default();
+        builder.draw_rect((0., 0.), (4., 4.), Color::WHITE);
+        builder.push_scissor(0, 0, 100, 100);
+        builder.push_scissor(50, -10, 100, 20);
+        assert_eq!(builder.scissor(), Some((50, 0, 50, 10)));
+        builder.draw_rect((0., 0.), (4., 4.), Color::WHITE);
+        builder.draw_rect((0., 0.), (4., 4.), Color::WHITE);
+        builder.pop_scissor();
+        builder.pop_scissor();
+        builder.draw_rect((0., 0.), (4., 4.), Color::WHITE);
+
+        let pass = builder.build();
+        let scissors: Vec<_> = pass.data.iter().map(|data| data.scissor).collect();
+        assert_eq!(scissors, &[None, Some((50, 0, 50, 10)), None]);
+        assert_eq!(pass.data[1].indices.len(), 12);
+    }
+
+    #[test]
+    fn scissor_clamped_to_target() {
+        let size = UVec2::new(100, 50);
+        assert_eq!(clamp_scissor((-10, 10, 30, 100), size), (0, 10, 20, 40));
+        assert_eq!(clamp_scissor((200, 0, 30, 30), size), (100, 0, 0, 30));
+        assert_eq!(
+            clamp_scissor((10, 10, i32::MAX, i32::MAX), size),
+            (10, 10, 90, 40)
+        );
+        assert_eq!(
+            intersect_rects((0, 0, i32::MAX, i32::MAX), (i32::MAX, -5, i32::MAX, 10)),
+            (i32::MAX, 0, 0, 5)
+        );
+    }
+
+    #[test]
+    fn state_changes_split_batches() {
+        let mut builder = RenderPassBuilder::default();
+        for n in 0..100 {
+            builder.draw_rect((n as f32, 0.), (4., 4.), Color::WHITE);
+        }
+        builder.draw_hairline((0., 0.), (4., 4.), Color::WHITE);
+        builder.draw_hairline((4., 4.), (8., 0.), Color::WHITE);
+        builder.set_view(Mat4::from_scale((2., 2., 1.).into()));
+        builder.draw_hairline((0., 0.), (4., 4.), Color::WHITE);
+        builder.draw_point((0., 0.), Color::WHITE);
+
+        let pass = builder.build();
+        let topologies: Vec<_> = pass.data.iter().map(|data| data.topology).collect();
+        assert_eq!(
+            topologies,
+            &[
+                Topology::Triangles,
+                Topology::Lines,
+                Topology::Lines,
+                Topology::Points
+            ]
+        );
+        assert_eq!(pass.data[0].indices.len(), 600);
+        assert_eq!(pass.data[1].indices, &[0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn render_state_splits_batches() {
+        let mut builder = RenderPassBuilder::default();
+        builder.draw_rect((0., 0.), (4., 4.), Color::WHITE);
+        builder.set_blend_mode(BlendMode::Additive);
+        builder.draw_rect((0., 0.), (4., 4.), Color::WHITE);
+        builder.draw_rect((4., 0.), (4., 4.), Color::WHITE);
+        builder.set_depth_stencil(Some(DepthStencil::depth(wgpu::CompareFunction::Less, true)));
+        builder.draw_rect((0., 0.), (4., 4.), Color::WHITE);
+
+        let pass = builder.build();
+        let blends: Vec<_> = pass.data.iter().map(|data| data.state.blend).collect();
+        assert_eq!(
+            blends,
+            &[BlendMode::Alpha, BlendMode::Additive, BlendMode::Additive]
+        );
+        assert_eq!(pass.data[1].indices.len(), 12);
+        assert!(pass.data[2].state.depth_stencil.is_some());
+    }
+
+    #[test]
+    fn unchanged_resources_keep_batches() {
+        let mut builder = RenderPassBuilder::default();
+        builder.draw_rect((0., 0.), (4., 4.), Color::WHITE);
+        builder.set_texture(None);
+        builder.set_resource(2, 0, None);
+        builder.draw_rect((0., 0.), (4., 4.), Color::WHITE);
+
+        let pass = builder.build();
+        assert_eq!(pass.data.len(), 1);
+        assert!(pass.data[0].resources.is_empty());
+    }
+
+    #[test]
+    fn framebuffer_textures_split_batches() {
+        let framebuffer = Rc::new(FrameBuffer::default());
+        let mut builder = RenderPassBuilder::default();
+        builder.set_framebuffer_texture(framebuffer.clone(), Attachment::Color(0));
+        builder.draw_rect((0., 0.), (4., 4.), Color::WHITE);
+        builder.set_framebuffer_texture(framebuffer.clone(), Attachment::Color(0));
+        builder.draw_rect((0., 0.), (4., 4.), Color::WHITE);
+        builder.set_framebuffer_texture(framebuffer.clone(), Attachment::DepthStencil);
+        builder.draw_rect((0., 0.), (4., 4.), Color::WHITE);
+        builder.set_framebuffer_texture(Rc::new(FrameBuffer::default()), Attachment::DepthStencil);
+        builder.draw_rect((0., 0.), (4., 4.), Color::WHITE);
+        builder.set_texture(None);
+        builder.draw_rect((0., 0.), (4., 4.), Color::WHITE);
+
+        let pass = builder.build();
+        let resources: Vec<_> = pass.data.iter().map(|data| data.resources.len()).collect();
+        assert_eq!(resources, &[1, 1, 1, 0]);
+        assert_eq!(pass.data[0].indices.len(), 12);
+    }
+
+    #[test]
+    fn texture_index_keeps_batches() {
+        let mut builder = RenderPassBuilder::default();
+        builder.draw_rect((0., 0.), (4., 4.), Color::WHITE);
+        builder.set_texture_index(2);
+        builder.draw_rect((0., 0.), (4., 4.), Color::WHITE);
+
+        let pass = builder.build();
+        assert_eq!(pass.data.len(), 1);
+        let vertices = &pass.data[0].vertices;
+        assert_eq!(vertices[0], vertices[4].with_texture_index(0));
+        assert_eq!(vertices[4], vertices[0].with_texture_index(2));
+    }
+
+    #[test]
+    fn build_drops_empty_batches() {
+        let mut builder = RenderPassBuilder::default();
+        builder.draw_polygon([(0., 0.), (1., 0.)], Color::RED);
+        assert!(builder.build().is_empty());
+
+        assert!(!RenderPassBuilder::default()
+            .clear_depth(1.)
+            .build()
+            .is_empty());
+        let mut builder = RenderPassBuilder::default();
+        builder.set_clear_stencil(0);
+        assert!(!builder.build().is_empty());
+
+        let mut builder = RenderPassBuilder::default().clear_color(Color::BLACK);
+        builder.draw_circle((0., 0.), 16., Color::RED);
+        let pass = builder.build();
+        assert_eq!(pass.data.len(), 1);
+        assert_eq!(
+            pass.data[0].indices.len(),
+            pass.data[0].vertices.len() * 3 - 3
+        );
+    }
+}
@@ -0,0 +1,126 @@
+use anyhow::{ensure, Result};
+use std::ops::Range;
+use wgpu::{BufferAddress, BufferSlice, BufferUsages, Device, Queue};
+
+/// A GPU buffer that gets refilled every frame.
+///
+/// The buffer is only reallocated when a frame needs more room than it has, in which case its
+/// capacity is doubled until everything fits.
+#[derive(Debug)]
+pub struct StreamBuffer {
+    buffer: wgpu::Buffer,
+    usage: BufferUsages,
+    alignment: BufferAddress,
+    offset: BufferAddress,
+    uploaded: BufferAddress,
+}
+
+impl StreamBuffer {
+    pub const INITIAL_CAPACITY: BufferAddress = 1 << 16;
+
+    /// Creates a buffer for `usage`, where every write starts on a multiple of `alignment`.
+    pub fn new(device: &Device, usage: BufferUsages, alignment: BufferAddress) -> Self {
+        Self {
+            buffer: new_buffer(device, usage, Self::INITIAL_CAPACITY),
+            usage,
+            alignment: alignment.max(wgpu::COPY_BUFFER_ALIGNMENT),
+            offset: 0,
+            uploaded: 0,
+        }
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    pub fn capacity(&self) -> BufferAddress {
+        self.buffer.size()
+    }
+
+    /// Bytes written since the start of the frame.
+    pub fn uploaded(&self) -> BufferAddress {
+        self.uploaded
+    }
+
+    /// How much room a write of `size` bytes takes up, including padding.
+    pub fn aligned(&self, size: BufferAddress) -> BufferAddress {
+        align_to(size, self.alignment)
+    }
+
+    /// Starts writing from the beginning of the buffer again, making sure there is room for
+    /// `size` bytes of aligned writes. Returns whether the buffer had to be reallocated.
+    ///
+    /// Anything written in previous frames is left for the GPU, as writes are queued
+    /// behind any submission still reading from it.
+    pub fn begin_frame(&mut self, device: &Device, size: BufferAddress) -> bool {
+        self.offset = 0;
+        self.uploaded = 0;
+
+        if size <= self.capacity() {
+            return false;
+        }
+
+        let capacity = grown_capacity(self.capacity(), size);
+        log::debug!("growing {:?} stream buffer to {capacity} bytes", self.usage);
+        self.buffer = new_buffer(device, self.usage, capacity);
+        true
+    }
+
+    /// Writes `bytes` after everything else written this frame, returning where they ended up.
+    pub fn write(&mut self, queue: &Queue, bytes: &[u8]) -> Result<Range<BufferAddress>> {
+        let size = bytes.len() as BufferAddress;
+        let start = self.offset;
+        ensure!(
+            start + size <= self.capacity(),
+            "stream buffer is out of room ({} bytes needed, {} left)",
+            size,
+            self.capacity() - start
+        );
+
+        queue.write_buffer(&self.buffer, start, bytes);
+        self.offset = start + self.aligned(size);
+        self.uploaded += size;
+
+        Ok(start..start + size)
+    }
+
+    pub fn slice(&self, range: Range<BufferAddress>) -> BufferSlice<'_> {
+        self.buffer.slice(range)
+    }
+}
+
+fn new_buffer(device: &Device, usage: BufferUsages, size: BufferAddress) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size,
+        usage: usage | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+fn align_to(size: BufferAddress, alignment: BufferAddress) -> BufferAddress {
+    size.div_ceil(alignment) * alignment
+}
+
+/// Doubles `capacity` until it's at least `required`.
+fn grown_capacity(capacity: BufferAddress, required: BufferAddress) -> BufferAddress {
+    let mut capacity = capacity.max(wgpu::COPY_BUFFER_ALIGNMENT);
+    while capacity < required {
+        capacity *= 2;
+    }
+    capacity
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn growth() {
+        assert_eq!(grown_capacity(1024, 1000), 1024);
+        assert_eq!(grown_capacity(1024, 1025), 2048);
+        assert_eq!(grown_capacity(1024, 5000), 8192);
+        assert_eq!(align_to(65, 256), 256);
+        assert_eq!(align_to(20, 4), 20);
+    }
+}
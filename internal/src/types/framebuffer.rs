@@ -1,8 +1,19 @@
+use anyhow::{anyhow, ensure, Result};
+use glam::UVec2;
 use wgpu::{
-    BlendState, Color, ColorTargetState, ColorWrites, Operations, RenderPassColorAttachment,
+    Color, Device, Operations, RenderPassColorAttachment, RenderPassDepthStencilAttachment,
     TextureFormat, TextureView,
 };
 
+use super::texture::new_wgpu_texture;
+
+/// Picks one of a [`FrameBuffer`]'s attachments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Attachment {
+    Color(usize),
+    DepthStencil,
+}
+
 #[derive(Debug, Default)]
 pub struct FrameBuffer {
     pub color_attachments: [Option<RenderAttachment>; Self::MAXCOLORATTACHMENTS],
@@ -10,7 +21,8 @@ pub struct FrameBuffer {
 }
 
 impl FrameBuffer {
-    pub const MAXCOLORATTACHMENTS: usize = 8;
+    // shared with `purs`, which checks fragment outputs against it.
+    pub const MAXCOLORATTACHMENTS: usize = purs::MAX_COLOR_ATTACHMENTS;
 
     pub fn new(
         color_attachments: [Option<RenderAttachment>; 8],
@@ -22,34 +34,37 @@ impl FrameBuffer {
         }
     }
 
-    pub fn color_target_states(
-        &mut self,
-        blends: &[Option<BlendState>],
-    ) -> [Option<ColorTargetState>; 8] {
-        let mut states: [Option<ColorTargetState>; 8] = Default::default();
-
-        for (index, color_attachment) in self.color_attachments.iter().enumerate() {
-            states[index] = color_attachment
-                .as_ref()
-                .map(|a| a.color_target_state(blends.get(index).and_then(|b| *b)));
+    pub fn attachment(&self, attachment: Attachment) -> Option<&RenderAttachment> {
+        match attachment {
+            Attachment::Color(index) => self.color_attachments.get(index)?.as_ref(),
+            Attachment::DepthStencil => self.depth_stencil_attachment.as_ref(),
         }
+    }
 
-        states
+    /// The formats of the attachments, with the color ones up to the last one that is set.
+    pub fn formats(&self) -> Result<TargetFormats> {
+        let describe = |attachment: &RenderAttachment| {
+            (attachment.format, attachment.sample_count, attachment.size)
+        };
+        TargetFormats::new(
+            &self
+                .color_attachments
+                .iter()
+                .map(|attachment| attachment.as_ref().map(describe))
+                .collect::<Vec<_>>(),
+            self.depth_stencil_attachment.as_ref().map(describe),
+        )
     }
 
     pub fn color_attachments(
-        &mut self,
+        &self,
         ops: [Operations<Color>; 8],
     ) -> [Option<RenderPassColorAttachment>; 8] {
         let mut color_attachments: [Option<RenderPassColorAttachment>; 8] = Default::default();
 
         for (index, color_attachment) in self.color_attachments.iter().enumerate() {
             if let Some(color_attachment) = color_attachment {
-                color_attachments[index] = Some(RenderPassColorAttachment {
-                    view: &color_attachment.view,
-                    resolve_target: None,
-                    ops: ops[index],
-                })
+                color_attachments[index] = Some(color_attachment.color_attachment(ops[index]))
             }
         }
 
@@ -57,19 +72,270 @@ impl FrameBuffer {
     }
 }
 
+/// The attachment formats of a render target, which the pipelines drawing into it have to
+/// match.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TargetFormats {
+    pub color: Vec<Option<TextureFormat>>,
+    pub depth_stencil: Option<TextureFormat>,
+    pub sample_count: u32,
+}
+
+impl TargetFormats {
+    /// Checks that attachments, given as their format, sample count and size, can be drawn
+    /// into by the same pass.
+    pub(crate) fn new(
+        color: &[Option<(TextureFormat, u32, UVec2)>],
+        depth_stencil: Option<(TextureFormat, u32, UVec2)>,
+    ) -> Result<Self> {
+        let len = color
+            .iter()
+            .rposition(Option::is_some)
+            .map_or(0, |index| index + 1);
+
+        ensure!(
+            color
+                .iter()
+                .flatten()
+                .all(|(format, ..)| !format.is_depth_stencil_format()),
+            "FrameBuffer color attachments can't have a depth format"
+        );
+        ensure!(
+            depth_stencil
+                .iter()
+                .all(|(format, ..)| format.is_depth_stencil_format()),
+            "FrameBuffer depth attachment needs a depth format"
+        );
+
+        let mut attachments = color
+            .iter()
+            .chain(std::iter::once(&depth_stencil))
+            .flatten();
+        let &(_, sample_count, size) = attachments
+            .next()
+            .ok_or_else(|| anyhow!("FrameBuffer has no attachments"))?;
+        for &(_, other_sample_count, other_size) in attachments {
+            ensure!(
+                other_sample_count == sample_count,
+                "FrameBuffer attachments have different sample counts"
+            );
+            ensure!(
+                other_size == size,
+                "FrameBuffer attachments have different sizes"
+            );
+        }
+
+        Ok(Self {
+            color: color[..len]
+                .iter()
+                .map(|attachment| attachment.map(|(format, ..)| format))
+                .collect(),
+            depth_stencil: depth_stencil.map(|(format, ..)| format),
+            sample_count,
+        })
+    }
+}
+
 #[derive(Debug)]
 pub struct RenderAttachment {
     view: TextureView,
+    /// Where a multisampled attachment is resolved to at the end of a pass.
+    resolve_target: Option<TextureView>,
+    /// The texture behind [`resolved_view`](Self::resolved_view), when the attachment made it.
+    texture: Option<wgpu::Texture>,
     pub format: TextureFormat,
-    depth_stencil: bool,
+    pub size: UVec2,
+    pub sample_count: u32,
 }
 
 impl RenderAttachment {
-    pub fn color_target_state(&self, blend: Option<BlendState>) -> wgpu::ColorTargetState {
-        ColorTargetState {
-            format: self.format,
-            blend,
-            write_mask: ColorWrites::all(),
+    pub(crate) fn new(view: TextureView, format: TextureFormat, size: UVec2) -> Self {
+        RenderAttachment {
+            view,
+            resolve_target: None,
+            texture: None,
+            format,
+            size,
+            sample_count: 1,
+        }
+    }
+
+    /// Creates a color attachment. When `sample_count` is more than 1 it is multisampled, and
+    /// resolved into a texture of its own at the end of every pass.
+    ///
+    /// [`Internal::color_attachment`](crate::Internal::color_attachment) also checks that the
+    /// device supports the sample count.
+    pub fn color(
+        device: &Device,
+        size: UVec2,
+        format: TextureFormat,
+        sample_count: u32,
+    ) -> Result<Self> {
+        ensure!(
+            !format.is_depth_stencil_format(),
+            "{format:?} can't be a color attachment"
+        );
+        let texture = new_wgpu_texture(device, size, format, true, 1);
+        let view = texture.create_view(&Default::default());
+        let attachment = if sample_count == 1 {
+            Self::new(view, format, size)
+        } else {
+            let multisampled = new_wgpu_texture(device, size, format, true, sample_count)
+                .create_view(&Default::default());
+            Self::multisampled(multisampled, view, format, size, sample_count)
+        };
+        Ok(Self {
+            texture: Some(texture),
+            ..attachment
+        })
+    }
+
+    /// Creates a depth and/or stencil attachment, with as many samples as the color
+    /// attachments it is used with.
+    pub fn depth(
+        device: &Device,
+        size: UVec2,
+        format: TextureFormat,
+        sample_count: u32,
+    ) -> Result<Self> {
+        ensure!(
+            format.is_depth_stencil_format(),
+            "{format:?} can't be a depth attachment"
+        );
+        let view = new_wgpu_texture(device, size, format, true, sample_count)
+            .create_view(&Default::default());
+        Ok(Self::new_depth(view, format, size, sample_count))
+    }
+
+    pub(crate) fn new_depth(
+        view: TextureView,
+        format: TextureFormat,
+        size: UVec2,
+        sample_count: u32,
+    ) -> Self {
+        RenderAttachment {
+            sample_count,
+            ..Self::new(view, format, size)
         }
     }
+
+    /// A multisampled attachment of `view`, resolved into `resolve_target`.
+    pub(crate) fn multisampled(
+        view: TextureView,
+        resolve_target: TextureView,
+        format: TextureFormat,
+        size: UVec2,
+        sample_count: u32,
+    ) -> Self {
+        RenderAttachment {
+            resolve_target: Some(resolve_target),
+            sample_count,
+            ..Self::new(view, format, size)
+        }
+    }
+
+    /// The view passes draw into, which is multisampled if the attachment is.
+    pub fn view(&self) -> &TextureView {
+        &self.view
+    }
+
+    /// The view holding what was drawn at the end of a pass, resolved if the attachment is
+    /// multisampled. This is the one to sample from afterwards.
+    pub fn resolved_view(&self) -> &TextureView {
+        self.resolve_target.as_ref().unwrap_or(&self.view)
+    }
+
+    /// [`resolved_view`](Self::resolved_view), and whether it's multisampled, which it only is
+    /// for multisampled depth attachments as they aren't resolved.
+    pub(crate) fn sampled_view(&self) -> (&TextureView, bool) {
+        match &self.resolve_target {
+            Some(resolve_target) => (resolve_target, false),
+            None => (&self.view, self.sample_count > 1),
+        }
+    }
+
+    /// The texture behind [`resolved_view`](Self::resolved_view), if the attachment owns it.
+    pub fn resolved_texture(&self) -> Option<&wgpu::Texture> {
+        self.texture.as_ref()
+    }
+
+    /// Attaches this to a render pass as a color attachment.
+    pub fn color_attachment(&self, ops: Operations<Color>) -> RenderPassColorAttachment<'_> {
+        RenderPassColorAttachment {
+            view: &self.view,
+            resolve_target: self.resolve_target.as_ref(),
+            ops,
+        }
+    }
+
+    /// Attaches this to a render pass as its depth-stencil attachment, with the ops of the
+    /// aspects its format has.
+    pub fn depth_stencil_attachment(
+        &self,
+        depth_ops: Operations<f32>,
+        stencil_ops: Operations<u32>,
+    ) -> RenderPassDepthStencilAttachment<'_> {
+        RenderPassDepthStencilAttachment {
+            view: &self.view,
+            depth_ops: self.format.has_depth_aspect().then_some(depth_ops),
+            stencil_ops: self.format.has_stencil_aspect().then_some(stencil_ops),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn target_formats() {
+        let size = UVec2::new(64, 32);
+        let color = (TextureFormat::Rgba8UnormSrgb, 4, size);
+        let depth = (TextureFormat::Depth32Float, 4, size);
+        assert_eq!(
+            TargetFormats::new(&[None, Some(color), None], Some(depth)).unwrap(),
+            TargetFormats {
+                color: vec![None, Some(color.0)],
+                depth_stencil: Some(depth.0),
+                sample_count: 4,
+            }
+        );
+        // depth prepasses have no color attachments.
+        assert_eq!(
+            TargetFormats::new(&[None], Some(depth)).unwrap(),
+            TargetFormats {
+                color: vec![],
+                depth_stencil: Some(depth.0),
+                sample_count: 4,
+            }
+        );
+
+        let error = |color: &[Option<(TextureFormat, u32, UVec2)>], depth| {
+            TargetFormats::new(color, depth).unwrap_err().to_string()
+        };
+        assert_eq!(
+            error(&[Some(color)], Some((TextureFormat::Depth32Float, 1, size))),
+            "FrameBuffer attachments have different sample counts"
+        );
+        assert_eq!(
+            error(
+                &[Some(color), Some((TextureFormat::Rgba16Float, 1, size))],
+                None
+            ),
+            "FrameBuffer attachments have different sample counts"
+        );
+        assert_eq!(
+            error(&[Some(color)], Some((depth.0, 4, UVec2::new(64, 64)))),
+            "FrameBuffer attachments have different sizes"
+        );
+        assert_eq!(
+            error(&[Some(depth)], None),
+            "FrameBuffer color attachments can't have a depth format"
+        );
+        assert_eq!(
+            error(&[Some(color)], Some(color)),
+            "FrameBuffer depth attachment needs a depth format"
+        );
+        assert_eq!(error(&[None], None), "FrameBuffer has no attachments");
+    }
 }
@@ -1,8 +1,10 @@
 //! internal types used in kittengpu.
 
 use bytemuck::{Pod, Zeroable};
+pub mod buffer;
 pub mod framebuffer;
 pub mod pipeline;
+pub mod resource;
 pub mod shader;
 pub mod texture;
 pub mod uniform;
@@ -1,7 +1,369 @@
-use super::framebuffer::FrameBuffer;
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use purs::EntryPoint;
+use wgpu::{
+    BlendComponent, BlendFactor, BlendOperation, BlendState, ColorTargetState, ColorWrites,
+    CompareFunction, DepthStencilState, StencilState, TextureFormat, TextureSampleType,
+};
+
+use super::framebuffer::{FrameBuffer, TargetFormats};
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub struct PipelineRequirements {
     pub primitive: wgpu::PrimitiveState,
     pub targets: [Option<wgpu::ColorTargetState>; FrameBuffer::MAXCOLORATTACHMENTS], // maxColorAttachments is 8 as per the spec.
+    pub depth_stencil: Option<DepthStencilState>,
+    pub sample_count: u32,
+    pub vertex_entry_point: String,
+    pub fragment_entry_point: String,
+}
+
+/// How a draw is combined with what is already in the target.
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum BlendMode {
+    /// Overwrites the target.
+    Replace,
+    /// Draws over the target by the draw's alpha.
+    #[default]
+    Alpha,
+    /// Like [`Alpha`](Self::Alpha), for colors already multiplied by their alpha.
+    Premultiplied,
+    /// Adds the color, scaled by its alpha, to the target.
+    Additive,
+    /// Multiplies the target by the color.
+    Multiply,
+}
+
+impl BlendMode {
+    pub fn state(self) -> BlendState {
+        match self {
+            BlendMode::Replace => BlendState::REPLACE,
+            BlendMode::Alpha => BlendState::ALPHA_BLENDING,
+            BlendMode::Premultiplied => BlendState::PREMULTIPLIED_ALPHA_BLENDING,
+            BlendMode::Additive => BlendState {
+                color: BlendComponent {
+                    src_factor: BlendFactor::SrcAlpha,
+                    dst_factor: BlendFactor::One,
+                    operation: BlendOperation::Add,
+                },
+                alpha: BlendComponent {
+                    src_factor: BlendFactor::One,
+                    dst_factor: BlendFactor::One,
+                    operation: BlendOperation::Add,
+                },
+            },
+            BlendMode::Multiply => BlendState {
+                color: BlendComponent {
+                    src_factor: BlendFactor::Dst,
+                    dst_factor: BlendFactor::Zero,
+                    operation: BlendOperation::Add,
+                },
+                alpha: BlendComponent::OVER,
+            },
+        }
+    }
+}
+
+/// The depth and stencil tests of a draw.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct DepthStencil {
+    pub depth_compare: CompareFunction,
+    pub depth_write: bool,
+    pub stencil: StencilState,
+    /// The value stencil tests compare against, which doesn't need a pipeline of its own.
+    pub stencil_reference: u32,
+}
+
+impl DepthStencil {
+    /// A depth test without stencil test.
+    pub fn depth(depth_compare: CompareFunction, depth_write: bool) -> Self {
+        Self {
+            depth_compare,
+            depth_write,
+            stencil: StencilState::default(),
+            stencil_reference: 0,
+        }
+    }
+
+    pub fn with_stencil(mut self, stencil: StencilState, reference: u32) -> Self {
+        self.stencil = stencil;
+        self.stencil_reference = reference;
+        self
+    }
+}
+
+/// Fixed-function state selectable per draw.
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone)]
+pub struct RenderState {
+    pub blend: BlendMode,
+    pub depth_stencil: Option<DepthStencil>,
+}
+
+/// Which entry points of a shader to build a pipeline from, `None` picking the first one the
+/// shader has for that stage.
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone)]
+pub struct EntryPoints {
+    pub vertex: Option<String>,
+    pub fragment: Option<String>,
+}
+
+impl EntryPoints {
+    pub fn new(vertex: impl Into<String>, fragment: impl Into<String>) -> Self {
+        Self {
+            vertex: Some(vertex.into()),
+            fragment: Some(fragment.into()),
+        }
+    }
+}
+
+/// The color targets of a pipeline where `fragment` draws into color attachments of `formats`,
+/// blended with `blend` where the format allows it.
+///
+/// Every `@location` the entry point writes needs an attachment of a matching type there, and
+/// every attachment has to be written.
+pub(crate) fn color_targets(
+    fragment: &EntryPoint,
+    formats: &[Option<TextureFormat>],
+    blend: BlendMode,
+    features: wgpu::Features,
+) -> Result<[Option<ColorTargetState>; FrameBuffer::MAXCOLORATTACHMENTS]> {
+    let attachments = formats.iter().flatten().count();
+    let name = &fragment.name;
+    ensure!(
+        formats.len() <= FrameBuffer::MAXCOLORATTACHMENTS,
+        "a target can't have more than {} color attachments",
+        FrameBuffer::MAXCOLORATTACHMENTS
+    );
+
+    let mut targets: [Option<ColorTargetState>; FrameBuffer::MAXCOLORATTACHMENTS] =
+        Default::default();
+    let mut matched = || -> Result<()> {
+        for output in &fragment.outputs {
+            let location = output.location as usize;
+            let format = formats
+                .get(location)
+                .copied()
+                .flatten()
+                .ok_or_else(|| anyhow!("@location({location}) has no color attachment"))?;
+
+            let kind = match format.sample_type(None) {
+                Some(TextureSampleType::Float { .. }) => naga::ScalarKind::Float,
+                Some(TextureSampleType::Uint) => naga::ScalarKind::Uint,
+                Some(TextureSampleType::Sint) => naga::ScalarKind::Sint,
+                _ => bail!("{format:?} can't be a color attachment"),
+            };
+            ensure!(
+                output.ty.kind == kind && output.ty.components >= format.components(),
+                "@location({location}) is {}, which can't be written to a {format:?} attachment",
+                output.ty
+            );
+
+            let blendable = format
+                .guaranteed_format_features(features)
+                .flags
+                .contains(wgpu::TextureFormatFeatureFlags::BLENDABLE);
+            targets[location] = Some(ColorTargetState {
+                format,
+                blend: blendable.then_some(blend.state()),
+                write_mask: ColorWrites::all(),
+            });
+        }
+
+        for (index, format) in formats.iter().enumerate() {
+            if let (Some(format), None) = (format, &targets[index]) {
+                bail!("color attachment {index} ({format:?}) isn't written");
+            }
+        }
+        Ok(())
+    };
+
+    matched()
+        .with_context(|| {
+            format!(
+                "fragment entry point '{name}' writes {} outputs, and the target has {attachments} color attachments",
+                fragment.outputs.len()
+            )
+        })
+        .map(|_| targets)
+}
+
+/// The depth-stencil state of a pipeline drawing into `target` with `state`.
+///
+/// A target with a depth attachment always needs one, if only to leave the depth untouched.
+pub(crate) fn depth_stencil_state(
+    target: &TargetFormats,
+    state: &RenderState,
+) -> Result<Option<DepthStencilState>> {
+    let Some(format) = target.depth_stencil else {
+        ensure!(
+            state.depth_stencil.is_none(),
+            "Depth and stencil tests need a target with a depth attachment"
+        );
+        return Ok(None);
+    };
+
+    let depth_stencil = state
+        .depth_stencil
+        .clone()
+        .unwrap_or_else(|| DepthStencil::depth(CompareFunction::Always, false));
+    ensure!(
+        !depth_stencil.stencil.is_enabled() || format.has_stencil_aspect(),
+        "Stencil tests need a stencil attachment, but the target's depth attachment is {format:?}"
+    );
+    Ok(Some(DepthStencilState {
+        format,
+        depth_write_enabled: depth_stencil.depth_write,
+        depth_compare: depth_stencil.depth_compare,
+        stencil: depth_stencil.stencil,
+        bias: Default::default(),
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use purs::ShaderReflection;
+
+    use super::*;
+
+    fn gbuffer() -> EntryPoint {
+        let reflection = ShaderReflection::from_wgsl(
+            "
+            struct GBuffer {
+                @location(0) albedo: vec4<f32>,
+                @location(1) normal: vec4<f32>,
+                @location(2) material: vec4<u32>,
+            }
+
+            @fragment
+            fn gbuffer() -> GBuffer {
+                return GBuffer(vec4<f32>(), vec4<f32>(), vec4<u32>());
+            }
+            ",
+        )
+        .unwrap();
+        reflection.entry_points[0].clone()
+    }
+
+    #[test]
+    fn multiple_targets() {
+        let targets = color_targets(
+            &gbuffer(),
+            &[
+                Some(TextureFormat::Rgba8UnormSrgb),
+                Some(TextureFormat::Rgba16Float),
+                Some(TextureFormat::Rgba8Uint),
+            ],
+            BlendMode::Alpha,
+            wgpu::Features::empty(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            targets[..4]
+                .iter()
+                .map(|target| target.as_ref().map(|target| (target.format, target.blend)))
+                .collect::<Vec<_>>(),
+            [
+                Some((
+                    TextureFormat::Rgba8UnormSrgb,
+                    Some(BlendState::ALPHA_BLENDING)
+                )),
+                Some((TextureFormat::Rgba16Float, Some(BlendState::ALPHA_BLENDING))),
+                // integer formats can't be blended.
+                Some((TextureFormat::Rgba8Uint, None)),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn mismatched_targets() {
+        let error = |formats: &[Option<TextureFormat>]| {
+            format!(
+                "{:#}",
+                color_targets(
+                    &gbuffer(),
+                    formats,
+                    BlendMode::Replace,
+                    wgpu::Features::empty()
+                )
+                .unwrap_err()
+            )
+        };
+
+        assert_eq!(
+            error(&[Some(TextureFormat::Bgra8UnormSrgb)]),
+            "fragment entry point 'gbuffer' writes 3 outputs, and the target has 1 color attachments: @location(1) has no color attachment"
+        );
+        assert_eq!(
+            error(&[
+                Some(TextureFormat::Rgba8Unorm),
+                Some(TextureFormat::Rgba8Unorm),
+                Some(TextureFormat::Rgba8Unorm),
+            ]),
+            "fragment entry point 'gbuffer' writes 3 outputs, and the target has 3 color attachments: @location(2) is vec4<u32>, which can't be written to a Rgba8Unorm attachment"
+        );
+        assert_eq!(
+            error(&[
+                Some(TextureFormat::Rgba8Unorm),
+                Some(TextureFormat::Rgba8Unorm),
+                Some(TextureFormat::Rgba8Uint),
+                Some(TextureFormat::R32Float),
+            ]),
+            "fragment entry point 'gbuffer' writes 3 outputs, and the target has 4 color attachments: color attachment 3 (R32Float) isn't written"
+        );
+    }
+
+    #[test]
+    fn depth_stencil() {
+        let mut target = TargetFormats {
+            color: vec![Some(TextureFormat::Rgba8UnormSrgb)],
+            depth_stencil: None,
+            sample_count: 1,
+        };
+        let depth = RenderState {
+            depth_stencil: Some(DepthStencil::depth(CompareFunction::Less, true)),
+            ..Default::default()
+        };
+        assert_eq!(
+            depth_stencil_state(&target, &RenderState::default()).unwrap(),
+            None
+        );
+        assert!(depth_stencil_state(&target, &depth).is_err());
+
+        target.depth_stencil = Some(TextureFormat::Depth32Float);
+        let state = depth_stencil_state(&target, &RenderState::default())
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            (state.depth_compare, state.depth_write_enabled),
+            (CompareFunction::Always, false)
+        );
+        let state = depth_stencil_state(&target, &depth).unwrap().unwrap();
+        assert_eq!(
+            (state.depth_compare, state.depth_write_enabled),
+            (CompareFunction::Less, true)
+        );
+
+        let face = wgpu::StencilFaceState {
+            compare: CompareFunction::Equal,
+            ..Default::default()
+        };
+        let stencil = RenderState {
+            depth_stencil: Some(
+                DepthStencil::depth(CompareFunction::Always, false).with_stencil(
+                    StencilState {
+                        front: face,
+                        back: face,
+                        read_mask: !0,
+                        write_mask: 0,
+                    },
+                    1,
+                ),
+            ),
+            ..Default::default()
+        };
+        assert!(depth_stencil_state(&target, &stencil).is_err());
+        target.depth_stencil = Some(TextureFormat::Depth24PlusStencil8);
+        assert!(depth_stencil_state(&target, &stencil).unwrap().is_some());
+    }
 }
@@ -0,0 +1,130 @@
+use super::{
+    framebuffer::{Attachment, FrameBuffer},
+    texture::Texture,
+};
+use arena::Key;
+use std::rc::Rc;
+use wgpu::{Buffer, BufferAddress, BufferSize, Sampler};
+
+/// Something bound to one of a shader's bindings, for the batches drawn while it's set with
+/// [`RenderPassBuilder::set_resource`](crate::renderpass::RenderPassBuilder::set_resource).
+#[derive(Debug, Clone)]
+pub enum Resource {
+    Texture(Key<Texture>),
+    /// The textures of a `binding_array`, which needs exactly as many. Vertices pick one with
+    /// [`RenderPassBuilder::set_texture_index`](crate::renderpass::RenderPassBuilder::set_texture_index).
+    Textures(Rc<[Key<Texture>]>),
+    /// What an earlier pass drew into an attachment, resolved if it's multisampled. It can't
+    /// be sampled by passes drawing into the same framebuffer.
+    Attachment {
+        framebuffer: Rc<FrameBuffer>,
+        attachment: Attachment,
+    },
+    Sampler(Key<Sampler>),
+    /// `size` bytes of a buffer from `offset`, or everything past `offset` if `None`.
+    Buffer {
+        buffer: Key<Buffer>,
+        offset: BufferAddress,
+        size: Option<BufferSize>,
+    },
+}
+
+impl PartialEq for Resource {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Texture(a), Self::Texture(b)) => a == b,
+            (Self::Textures(a), Self::Textures(b)) => a == b,
+            (
+                Self::Attachment {
+                    framebuffer: a,
+                    attachment: a_attachment,
+                },
+                Self::Attachment {
+                    framebuffer: b,
+                    attachment: b_attachment,
+                },
+            ) => Rc::ptr_eq(a, b) && a_attachment == b_attachment,
+            (Self::Sampler(a), Self::Sampler(b)) => a == b,
+            (
+                Self::Buffer {
+                    buffer: a,
+                    offset: a_offset,
+                    size: a_size,
+                },
+                Self::Buffer {
+                    buffer: b,
+                    offset: b_offset,
+                    size: b_size,
+                },
+            ) => a == b && a_offset == b_offset && a_size == b_size,
+            _ => false,
+        }
+    }
+}
+
+impl From<Key<Texture>> for Resource {
+    fn from(texture: Key<Texture>) -> Self {
+        Self::Texture(texture)
+    }
+}
+
+impl From<Key<Sampler>> for Resource {
+    fn from(sampler: Key<Sampler>) -> Self {
+        Self::Sampler(sampler)
+    }
+}
+
+impl From<Key<Buffer>> for Resource {
+    fn from(buffer: Key<Buffer>) -> Self {
+        Self::Buffer {
+            buffer,
+            offset: 0,
+            size: None,
+        }
+    }
+}
+
+/// Checks that a texture of `format` can be bound where `sample_type` is expected.
+/// Unfilterable float bindings also take depth textures.
+pub(crate) fn check_sample_type(
+    format: wgpu::TextureFormat,
+    sample_type: wgpu::TextureSampleType,
+) -> anyhow::Result<()> {
+    use wgpu::TextureSampleType::*;
+
+    let compatible = match (format.sample_type(None), sample_type) {
+        (Some(Float { filterable }), Float { filterable: needed }) => filterable || !needed,
+        (Some(Depth), Depth | Float { filterable: false }) => true,
+        (Some(Uint), Uint) | (Some(Sint), Sint) => true,
+        _ => false,
+    };
+    anyhow::ensure!(
+        compatible,
+        "a {format:?} texture can't be bound where a {sample_type:?} texture is expected"
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sample_types() {
+        use wgpu::{TextureFormat, TextureSampleType::*};
+
+        let filterable = Float { filterable: true };
+        let unfilterable = Float { filterable: false };
+        assert!(check_sample_type(TextureFormat::Rgba8UnormSrgb, filterable).is_ok());
+        assert!(check_sample_type(TextureFormat::Rgba8UnormSrgb, unfilterable).is_ok());
+        assert!(check_sample_type(TextureFormat::R32Float, filterable).is_err());
+        assert!(check_sample_type(TextureFormat::R32Float, unfilterable).is_ok());
+        assert!(check_sample_type(TextureFormat::Depth32Float, Depth).is_ok());
+        assert!(check_sample_type(TextureFormat::Depth32Float, unfilterable).is_ok());
+        assert!(check_sample_type(TextureFormat::Depth32Float, filterable).is_err());
+        // both aspects can't be sampled at once.
+        assert!(check_sample_type(TextureFormat::Depth24PlusStencil8, Depth).is_err());
+        assert!(check_sample_type(TextureFormat::R32Uint, Uint).is_ok());
+        assert!(check_sample_type(TextureFormat::R32Uint, Sint).is_err());
+    }
+}
@@ -1,51 +1,80 @@
-mod parsing;
-use anyhow::Result;
-use naga::back::wgsl::{write_string, WriterFlags};
-use naga::valid::{Capabilities, ValidationFlags, Validator};
+use std::collections::HashMap;
+
+use anyhow::{ensure, Context, Result};
+use purs::{ShaderReflection, MAX_BIND_GROUPS};
+
+use super::vertex::Vertex;
 
 /// Internal shader type.
 #[derive(Debug)]
 pub struct Shader {
     pub module: wgpu::ShaderModule,
-    pub bind_group_layouts: [wgpu::BindGroupLayout; 4],
+    pub bind_group_layouts: [wgpu::BindGroupLayout; MAX_BIND_GROUPS],
     pub pipeline_layout: wgpu::PipelineLayout,
-    pub attachments: usize,
+    pub reflection: ShaderReflection,
+    /// [`purs::source_hash`] of the WGSL it was created from.
+    pub source_hash: u64,
+    /// The [`Vertex`](super::vertex::Vertex) attributes each vertex entry point reads.
+    pub vertex_attributes: HashMap<String, Vec<wgpu::VertexAttribute>>,
 }
 
-/// Loads shader in from file.
-pub fn load_shader(device: &wgpu::Device, source: &str) -> Result<Shader> {
-    let module = naga::front::wgsl::parse_str(source)?;
-    let info = Validator::new(ValidationFlags::all(), Capabilities::all()).validate(&module)?;
-
-    let layout_entries = parsing::generate_layout_entries(&module)?;
-    let (bind_group_layouts, pipeline_layout) = {
-        if layout_entries.iter().all(|v| v.is_empty()) {
-            (
-                parsing::generate_bind_group_layouts(device, layout_entries),
-                parsing::generate_pipeline_layout(device, &[]),
-            )
-        } else {
-            let bind_group_layouts = parsing::generate_bind_group_layouts(device, layout_entries);
-            let bind_group_refs = {
-                let [ref a, ref b, ref c, ref d] = bind_group_layouts;
-                &[a, b, c, d]
-            };
-            let pipeline_layout = parsing::generate_pipeline_layout(device, bind_group_refs);
-            (bind_group_layouts, pipeline_layout)
-        }
-    };
+impl Shader {
+    /// Gets the vertex entry point called `name`, or the first one if no name is given.
+    pub fn vertex_entry_point(&self, name: Option<&str>) -> Result<&str> {
+        self.reflection
+            .entry_point(naga::ShaderStage::Vertex, name)
+            .map(|entry_point| entry_point.name.as_str())
+    }
 
-    let attachments = parsing::query_attachments(&module)?;
+    /// Gets the fragment entry point called `name`, or the first one if no name is given.
+    pub fn fragment_entry_point(&self, name: Option<&str>) -> Result<&str> {
+        self.reflection
+            .entry_point(naga::ShaderStage::Fragment, name)
+            .map(|entry_point| entry_point.name.as_str())
+    }
+}
+
+/// Creates a shader from already reflected WGSL `source`, without parsing it again.
+pub fn shader_from_reflection(
+    device: &wgpu::Device,
+    source: &str,
+    reflection: ShaderReflection,
+) -> Result<Shader> {
+    let missing_features = reflection.features - device.features();
+    ensure!(
+        missing_features.is_empty(),
+        "Shader needs {missing_features:?}, which this device doesn't support"
+    );
+
+    let bind_group_layouts = purs::gen::generate_bind_group_layouts(device, &reflection);
+    // every group in the layout has to be bound to draw, so it stops at the last one used.
+    let pipeline_layout = purs::gen::generate_pipeline_layout(
+        device,
+        &bind_group_layouts[..reflection.bind_group_count()]
+            .iter()
+            .collect::<Vec<_>>(),
+    );
+
+    let vertex_attributes = reflection
+        .entry_points(naga::ShaderStage::Vertex)
+        .map(|entry_point| {
+            purs::gen::vertex_attributes(entry_point, &Vertex::BUFFER_LAYOUT)
+                .map(|attributes| (entry_point.name.clone(), attributes))
+        })
+        .collect::<Result<_>>()
+        .context("Shader inputs don't match the Vertex type")?;
 
     let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
         label: None,
-        source: wgpu::ShaderSource::Wgsl(write_string(&module, &info, WriterFlags::all())?.into()),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
     });
     Ok(Shader {
         module,
         bind_group_layouts,
         pipeline_layout,
-        attachments,
+        reflection,
+        source_hash: purs::source_hash(source),
+        vertex_attributes,
     })
 }
 
@@ -59,11 +88,16 @@ mod test {
     fn serde() {
         let shader = include_str!("../../../../shaders/main.wgsl");
         let module = naga::front::wgsl::parse_str(shader).expect("unable to parse shader");
-        let vertex_name =
-            parsing::get_entrypoint_name(&module, &naga::ShaderStage::Vertex).unwrap();
-
-        let fragment_name =
-            parsing::get_entrypoint_name(&module, &naga::ShaderStage::Fragment).unwrap();
+        let reflection = ShaderReflection::new(&module).unwrap();
+        let vertex_name = &reflection
+            .entry_point(naga::ShaderStage::Vertex, None)
+            .unwrap()
+            .name;
+
+        let fragment_name = &reflection
+            .entry_point(naga::ShaderStage::Fragment, None)
+            .unwrap()
+            .name;
 
         println!("vertex_name: {vertex_name}\nfragment_name: {fragment_name}");
         println!(
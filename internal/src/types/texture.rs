@@ -1,6 +1,29 @@
+use std::borrow::Cow;
+
+use anyhow::{anyhow, ensure, Context, Result};
 use glam::UVec2;
+use image::RgbaImage;
 use wgpu::{Device, Queue};
 
+/// How the color values of a texture are encoded.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum ColorSpace {
+    /// Colors meant to be seen, like sprites, which are converted to linear when sampled.
+    #[default]
+    Srgb,
+    /// Data that isn't a color, like normal maps or lookup tables.
+    Linear,
+}
+
+impl ColorSpace {
+    pub fn format(self) -> wgpu::TextureFormat {
+        match self {
+            ColorSpace::Srgb => wgpu::TextureFormat::Rgba8UnormSrgb,
+            ColorSpace::Linear => wgpu::TextureFormat::Rgba8Unorm,
+        }
+    }
+}
+
 // Internal texture type.
 #[derive(Debug)]
 pub struct Texture {
@@ -10,6 +33,45 @@ pub struct Texture {
 }
 
 impl Texture {
+    /// Creates a texture holding `image`, which can't be empty.
+    pub fn from_image(
+        device: &Device,
+        queue: &Queue,
+        image: &RgbaImage,
+        color_space: ColorSpace,
+    ) -> Result<Self> {
+        let size = UVec2::from(image.dimensions());
+        check_size(size)?;
+        let format = color_space.format();
+        let texture = new_wgpu_texture(device, size, format, false, 1);
+        queue.write_texture(
+            texture.as_image_copy(),
+            image,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * size.x),
+                rows_per_image: None,
+            },
+            texture.size(),
+        );
+        Ok(Self {
+            texture,
+            format,
+            size,
+        })
+    }
+
+    /// Decodes an image file of any format the `image` crate knows, and uploads it.
+    pub fn from_bytes(
+        device: &Device,
+        queue: &Queue,
+        bytes: &[u8],
+        color_space: ColorSpace,
+    ) -> Result<Self> {
+        let image = image::load_from_memory(bytes).context("Could not decode image")?;
+        Self::from_image(device, queue, &image.into_rgba8(), color_space)
+    }
+
     pub fn get_view(&self) -> wgpu::TextureView {
         self.texture.create_view(&Default::default())
     }
@@ -24,21 +86,189 @@ pub struct TextureMut<'a> {
 }
 
 impl<'a> TextureMut<'a> {
-    fn write_to_texture(&mut self) {
-        todo!()
+    pub fn new(texture: &'a mut Texture, device: &'a Device, queue: &'a Queue) -> Self {
+        Self {
+            texture,
+            device,
+            queue,
+        }
+    }
+
+    pub fn size(&self) -> UVec2 {
+        self.texture.size
+    }
+
+    /// Writes `image` into the texture with its top left corner at `origin`.
+    pub fn write_image(&mut self, origin: UVec2, image: &RgbaImage) -> Result<()> {
+        self.write_bytes(origin, image.dimensions().into(), image)
+    }
+
+    /// Writes tightly packed rows of texels into the `size` rectangle at `origin`.
+    pub fn write_bytes(&mut self, origin: UVec2, size: UVec2, bytes: &[u8]) -> Result<()> {
+        let row_size = check_write(
+            self.texture.format,
+            self.texture.size,
+            origin,
+            size,
+            bytes.len(),
+        )?;
+        if size.x == 0 || size.y == 0 {
+            return Ok(());
+        }
+
+        let (bytes, bytes_per_row) = pad_rows(bytes, row_size);
+        self.queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &self.texture.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: origin.x,
+                    y: origin.y,
+                    z: 0,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            &bytes,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(bytes_per_row),
+                rows_per_image: None,
+            },
+            wgpu::Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 1,
+            },
+        );
+        Ok(())
+    }
+
+    /// Replaces the whole texture with `image`, resizing it if needed.
+    pub fn replace(&mut self, image: &RgbaImage) -> Result<()> {
+        let size = image.dimensions().into();
+        check_size(size)?;
+        if size != self.texture.size {
+            self.recreate(size, false);
+        }
+        self.write_image(UVec2::ZERO, image)
+    }
+
+    /// Resizes the texture, keeping the part of its contents that still fits. Anything new is
+    /// transparent black.
+    pub fn resize(&mut self, size: UVec2) -> Result<()> {
+        check_size(size)?;
+        if size != self.texture.size {
+            self.recreate(size, true);
+        }
+        Ok(())
+    }
+
+    /// Swaps the texture for one of `size`, optionally copying over the old contents.
+    fn recreate(&mut self, size: UVec2, keep_contents: bool) {
+        let texture = new_wgpu_texture(self.device, size, self.texture.format, false, 1);
+
+        let copy = size.min(self.texture.size);
+        if keep_contents && copy.x > 0 && copy.y > 0 {
+            let mut encoder = self.device.create_command_encoder(&Default::default());
+            encoder.copy_texture_to_texture(
+                self.texture.texture.as_image_copy(),
+                texture.as_image_copy(),
+                wgpu::Extent3d {
+                    width: copy.x,
+                    height: copy.y,
+                    depth_or_array_layers: 1,
+                },
+            );
+            self.queue.submit(std::iter::once(encoder.finish()));
+        }
+
+        self.texture.texture = texture;
+        self.texture.size = size;
     }
 }
 
+/// Checks that `len` bytes of texels fill the `size` rectangle at `origin` in a texture of
+/// `texture_size`, returning the size of a row in bytes.
+fn check_write(
+    format: wgpu::TextureFormat,
+    texture_size: UVec2,
+    origin: UVec2,
+    size: UVec2,
+    len: usize,
+) -> Result<u32> {
+    let texel_size = format
+        .block_size(None)
+        .filter(|_| format.block_dimensions() == (1, 1))
+        .ok_or_else(|| anyhow!("Can't write texels of {format:?} textures"))?;
+    let fits = |origin: u32, size: u32, texture_size| {
+        origin
+            .checked_add(size)
+            .is_some_and(|end| end <= texture_size)
+    };
+    ensure!(
+        fits(origin.x, size.x, texture_size.x) && fits(origin.y, size.y, texture_size.y),
+        "Can't write {}x{} texels at {origin} into a {}x{} texture",
+        size.x,
+        size.y,
+        texture_size.x,
+        texture_size.y
+    );
+    // the rectangle fits in the texture, so this can't overflow.
+    let row_size = texel_size * size.x;
+    ensure!(
+        len as u64 == row_size as u64 * size.y as u64,
+        "{}x{} texels of {format:?} are {} bytes, not {len}",
+        size.x,
+        size.y,
+        row_size as u64 * size.y as u64
+    );
+    Ok(row_size)
+}
+
+/// Pads rows of `row_size` bytes to [`wgpu::COPY_BYTES_PER_ROW_ALIGNMENT`], returning the
+/// padded bytes and the padded row size.
+fn pad_rows(bytes: &[u8], row_size: u32) -> (Cow<'_, [u8]>, u32) {
+    let alignment = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    let padded_size = row_size.div_ceil(alignment) * alignment;
+    if padded_size == row_size {
+        return (Cow::Borrowed(bytes), row_size);
+    }
+
+    let mut padded = Vec::with_capacity(bytes.len() / row_size as usize * padded_size as usize);
+    for row in bytes.chunks(row_size as usize) {
+        padded.extend_from_slice(row);
+        padded.resize(padded.len() + (padded_size - row_size) as usize, 0);
+    }
+    (Cow::Owned(padded), padded_size)
+}
+
+/// Checks that a texture of `size` can be created, as wgpu doesn't allow empty ones.
+fn check_size(size: UVec2) -> Result<()> {
+    ensure!(
+        size.x > 0 && size.y > 0,
+        "Textures can't be empty, but this one would be {}x{}",
+        size.x,
+        size.y
+    );
+    Ok(())
+}
+
 /// create a new wgpu texture
 pub fn new_wgpu_texture(
     device: &Device,
     size: UVec2,
     format: wgpu::TextureFormat,
     render_target: bool,
+    sample_count: u32,
 ) -> wgpu::Texture {
+    // copying from textures lets them be resized without losing their contents.
     let usage = render_target
         .then(|| wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING)
-        .unwrap_or(wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING);
+        .unwrap_or(
+            wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::TEXTURE_BINDING,
+        );
 
     device.create_texture(&wgpu::TextureDescriptor {
         size: wgpu::Extent3d {
@@ -47,7 +277,7 @@ pub fn new_wgpu_texture(
             depth_or_array_layers: 1,
         },
         mip_level_count: 1,
-        sample_count: 1,
+        sample_count,
         dimension: wgpu::TextureDimension::D2,
         format,
         usage,
@@ -56,6 +286,139 @@ pub fn new_wgpu_texture(
     })
 }
 
+/// Checks that attachments of `format` can be multisampled `sample_count` times on `adapter`,
+/// and resolved.
+pub fn check_sample_count(
+    adapter: &wgpu::Adapter,
+    device: &Device,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+) -> Result<()> {
+    // without this feature only what WebGPU guarantees can be used.
+    let features = if device
+        .features()
+        .contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES)
+    {
+        adapter.get_texture_format_features(format)
+    } else {
+        format.guaranteed_format_features(device.features())
+    };
+    check_format_sample_count(format, features, sample_count)
+}
+
+/// Checks `sample_count` against the `features` of `format`.
+fn check_format_sample_count(
+    format: wgpu::TextureFormat,
+    features: wgpu::TextureFormatFeatures,
+    sample_count: u32,
+) -> Result<()> {
+    ensure!(
+        matches!(sample_count, 1 | 2 | 4 | 8),
+        "Sample count has to be 1, 2, 4 or 8, not {sample_count}"
+    );
+    ensure!(
+        features.flags.sample_count_supported(sample_count),
+        "{format:?} can't be multisampled {sample_count} times on this device"
+    );
+    ensure!(
+        sample_count == 1
+            || format.is_depth_stencil_format()
+            || features
+                .flags
+                .contains(wgpu::TextureFormatFeatureFlags::MULTISAMPLE_RESOLVE),
+        "Multisampled {format:?} attachments can't be resolved on this device"
+    );
+    Ok(())
+}
+
 pub fn new_depth_texture(device: &Device, size: UVec2) -> wgpu::Texture {
-    new_wgpu_texture(device, size, wgpu::TextureFormat::Depth32Float, true)
+    new_wgpu_texture(device, size, wgpu::TextureFormat::Depth32Float, true, 1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn empty_size() {
+        assert!(check_size(UVec2::new(1, 1)).is_ok());
+        assert_eq!(
+            check_size(UVec2::new(16, 0)).unwrap_err().to_string(),
+            "Textures can't be empty, but this one would be 16x0"
+        );
+    }
+
+    #[test]
+    fn sample_counts() {
+        let check = |format: wgpu::TextureFormat, sample_count| {
+            let features = format.guaranteed_format_features(wgpu::Features::empty());
+            check_format_sample_count(format, features, sample_count).map_err(|e| e.to_string())
+        };
+        let format = wgpu::TextureFormat::Rgba8UnormSrgb;
+        assert!(check(format, 1).is_ok());
+        assert!(check(format, 4).is_ok());
+        assert_eq!(
+            check(format, 3).unwrap_err(),
+            "Sample count has to be 1, 2, 4 or 8, not 3"
+        );
+        assert_eq!(
+            check(format, 16).unwrap_err(),
+            "Sample count has to be 1, 2, 4 or 8, not 16"
+        );
+        // WebGPU only guarantees 4.
+        assert_eq!(
+            check(format, 8).unwrap_err(),
+            "Rgba8UnormSrgb can't be multisampled 8 times on this device"
+        );
+        assert!(check(wgpu::TextureFormat::Depth32Float, 4).is_ok());
+        assert!(check(wgpu::TextureFormat::Rgba32Float, 4).is_err());
+    }
+
+    #[test]
+    fn write_checks() {
+        let format = wgpu::TextureFormat::Rgba8UnormSrgb;
+        let texture = UVec2::new(64, 32);
+        let check = |origin: UVec2, size: UVec2, len| {
+            check_write(format, texture, origin, size, len).map_err(|e| e.to_string())
+        };
+
+        assert_eq!(check(UVec2::new(60, 30), UVec2::new(4, 2), 32), Ok(16));
+        assert_eq!(check(UVec2::ZERO, UVec2::ZERO, 0), Ok(0));
+        assert_eq!(
+            check(UVec2::new(61, 0), UVec2::new(4, 2), 32).unwrap_err(),
+            "Can't write 4x2 texels at [61, 0] into a 64x32 texture"
+        );
+        assert_eq!(
+            check(UVec2::new(0, u32::MAX), UVec2::new(1, 1), 4).unwrap_err(),
+            "Can't write 1x1 texels at [0, 4294967295] into a 64x32 texture"
+        );
+        assert_eq!(
+            check(UVec2::ZERO, UVec2::new(4, 2), 31).unwrap_err(),
+            "4x2 texels of Rgba8UnormSrgb are 32 bytes, not 31"
+        );
+        assert!(check_write(
+            wgpu::TextureFormat::Bc1RgbaUnorm,
+            texture,
+            UVec2::ZERO,
+            UVec2::ONE,
+            8
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn row_padding() {
+        let bytes: Vec<u8> = (0..24).collect();
+        let (padded, bytes_per_row) = pad_rows(&bytes, 8);
+        assert_eq!(bytes_per_row, 256);
+        assert_eq!(padded.len(), 3 * 256);
+        assert_eq!(&padded[..8], &bytes[..8]);
+        assert!(padded[8..256].iter().all(|&byte| byte == 0));
+        assert_eq!(&padded[512..520], &bytes[16..]);
+
+        let bytes = vec![1; 512 * 2];
+        let (padded, bytes_per_row) = pad_rows(&bytes, 512);
+        assert!(matches!(padded, Cow::Borrowed(_)));
+        assert_eq!(bytes_per_row, 512);
+    }
 }
@@ -1,6 +1,6 @@
 use anyhow::Result;
 use bytemuck::{Pod, Zeroable};
-use glam::Vec2;
+use glam::{Mat3, Vec2};
 
 use super::Color;
 
@@ -8,9 +8,11 @@ use super::Color;
 #[repr(C)]
 #[derive(Debug, Default, PartialEq, Clone, Copy)]
 pub struct Vertex {
-    pos: Vec2,
+    pub(crate) pos: Vec2,
     tex: Vec2,
     col: Color,
+    /// Which texture of a `binding_array` to read, for shaders that take one.
+    texture_index: u32,
 }
 
 unsafe impl Pod for Vertex {}
@@ -21,31 +23,144 @@ impl Vertex {
     pub const BUFFER_LAYOUT: wgpu::VertexBufferLayout<'static> = wgpu::VertexBufferLayout {
         array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
         step_mode: wgpu::VertexStepMode::Vertex,
-        attributes: &wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Uint8x4],
+        attributes: &wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Uint8x4, 3 => Uint32],
     };
 
     pub fn new(pos: Vec2, tex: Vec2, col: impl Into<Color>) -> Self {
         let col = col.into();
-        Self { pos, tex, col }
+        Self {
+            pos,
+            tex,
+            col,
+            texture_index: 0,
+        }
+    }
+
+    /// Returns the vertex reading texture `index` of a `binding_array`.
+    pub fn with_texture_index(self, texture_index: u32) -> Self {
+        Self {
+            texture_index,
+            ..self
+        }
+    }
+
+    /// Returns the vertex with its position transformed by `matrix`.
+    pub fn transformed(self, matrix: &Mat3) -> Self {
+        Self {
+            pos: matrix.transform_point2(self.pos),
+            ..self
+        }
     }
 }
 
 #[cfg(test)]
 mod test {
+    use purs::ShaderReflection;
+
     use super::*;
 
+    const TEST_SHADER: &str = "
+        struct Vertex {
+            @location(0) pos: vec2<f32>,
+            //@location(1) tex: vec2<f32>,
+            @location(1) col: vec4<u32>
+        }
+        
+        struct Fragment {
+            @builtin(position) pos: vec4<f32>,
+            //@location(0) tex: vec2<f32>
+            @location(1) col: vec4<f32>
+        }
+        
+        struct Output {
+            @location(0) diffuse: vec4<f32>
+        }
+        
+        @group(0) @binding(0)
+        var texture: texture_2d<f32>;
+        @group(0) @binding(1)
+        var tex_sampler: sampler;
+        @group(1) @binding(0)
+        var<uniform> matrix: mat4x4<f32>;
+        
+        
+        // super simple vertex shader
+        @vertex
+        fn vertex(vert: Vertex) -> Fragment {
+            var frag: Fragment;
+            //frag.tex = vert.tex;
+            frag.pos = vec4<f32>(vert.pos, 1.0, 1.0);
+            frag.col = vec4<f32>(vert.col);
+            return frag;
+        }
+
+        @fragment
+        fn fragment(frag: Fragment) -> Output {
+            //return textureSample(texture, tex_sampler, frag.tex);
+            var output: Output;
+            output.diffuse = vec4<f32>(1.0, 1.0, 1.0, 1.0) * frag.col;
+            return output;
+        }
+    ";
+
+    #[test]
+    fn vertex_layout() {
+        let attributes = |source| {
+            let module = naga::front::wgsl::parse_str(source).unwrap();
+            let reflection = ShaderReflection::new(&module).unwrap();
+            let vertex = reflection
+                .entry_point(naga::ShaderStage::Vertex, None)
+                .unwrap();
+            purs::gen::vertex_attributes(vertex, &Vertex::BUFFER_LAYOUT)
+        };
+        // it doesn't read the texture index.
+        assert_eq!(
+            attributes(include_str!("../../../shaders/main.wgsl")).unwrap(),
+            &Vertex::BUFFER_LAYOUT.attributes[..3]
+        );
+
+        let atlas = attributes(
+            "@group(0) @binding(0) var textures: binding_array<texture_2d<f32>, 4>;
+            @vertex fn vertex(@location(0) pos: vec2<f32>, @location(3) index: u32) -> @builtin(position) vec4<f32> {
+                return vec4<f32>(pos, f32(textureDimensions(textures[index]).x), 1.0);
+            }",
+        )
+        .unwrap();
+        assert_eq!(
+            atlas,
+            &[
+                Vertex::BUFFER_LAYOUT.attributes[0],
+                Vertex::BUFFER_LAYOUT.attributes[3]
+            ]
+        );
+
+        // `col` sits at @location(1) here, where `Vertex` has its texture coordinates.
+        let error = attributes(TEST_SHADER).unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "vertex input @location(1) of 'vertex' is vec4<u32>, but the vertex type provides vec2<f32> (Float32x2)"
+        );
+    }
+
     #[test]
     fn test() {
         let v = &[Vertex {
             pos: Vec2::from((1., 0.)),
             tex: Vec2::from((0., 2.)),
             col: [0u8, 127u8, 255u8, 255u8].into(),
+            texture_index: 3,
         }];
         let b: &[u8] = bytemuck::cast_slice(v);
         assert_eq!(
             b,
-            &[0, 0, 128, 63, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 64, 0, 127, 255, 255]
+            &[0, 0, 128, 63, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 64, 0, 127, 255, 255, 3, 0, 0, 0]
         )
         //assert_eq!(b, &[0, 0, 128, 63, 0, 0, 0, 0, 0, 127, 255, 255])
     }
+
+    #[test]
+    fn layout() {
+        // purs checks shaders against its own copy of the layout.
+        assert_eq!(Vertex::BUFFER_LAYOUT, purs::VERTEX_LAYOUT);
+    }
 }
@@ -0,0 +1,440 @@
+use std::{collections::HashSet, num::NonZeroU32};
+
+use anyhow::{anyhow, bail, Result};
+use naga::{
+    proc::Layouter,
+    valid::{Capabilities, ValidationFlags, Validator},
+    GlobalVariable, Handle, TypeInner,
+};
+
+use crate::{Binding, MAX_BIND_GROUPS};
+
+/// Reflects the bind group layout entries of every global resource in the module.
+pub(crate) fn reflect_bindings(module: &naga::Module) -> Result<[Vec<Binding>; MAX_BIND_GROUPS]> {
+    let mut layouter = Layouter::default();
+    layouter.update(module.to_ctx())?;
+    let usage = ImageUsage::new(module);
+
+    let mut groups: [Vec<Binding>; MAX_BIND_GROUPS] = Default::default();
+    for (global, var) in module.global_variables.iter() {
+        let Some(binding) = &var.binding else {
+            continue;
+        };
+        let group = groups.get_mut(binding.group as usize).ok_or_else(|| {
+            anyhow!(
+                "@group({}) is out of range, shaders can only use {MAX_BIND_GROUPS} bind groups",
+                binding.group
+            )
+        })?;
+
+        let (ty, count) = binding_array(module, var.ty)?;
+        let ty = match var.space {
+            naga::AddressSpace::Handle => {
+                let ty = map_naga_inner_type_to_wgpu_binding_type(&module.types[ty])?;
+                usage.refine(global, ty)
+            }
+            space => map_address_space_to_wgpu_buffer_binding_type(space, layouter[ty].size)?,
+        };
+
+        group.push(Binding {
+            name: var.name.clone(),
+            entry: wgpu::BindGroupLayoutEntry {
+                binding: binding.binding,
+                visibility: visibility(module, global),
+                ty,
+                count,
+            },
+        });
+    }
+    Ok(groups)
+}
+
+/// The stages of the entry points that use a global, directly or through the functions they
+/// call.
+fn visibility(module: &naga::Module, global: Handle<GlobalVariable>) -> wgpu::ShaderStages {
+    module
+        .entry_points
+        .iter()
+        .filter(|entry_point| uses_global(module, &entry_point.function, global))
+        .fold(wgpu::ShaderStages::NONE, |stages, entry_point| {
+            stages | shader_stage(entry_point.stage)
+        })
+}
+
+/// Whether `function`, or a function it calls, refers to `global`.
+fn uses_global(
+    module: &naga::Module,
+    function: &naga::Function,
+    global: Handle<GlobalVariable>,
+) -> bool {
+    let mut callees = vec![];
+    calls(&function.body, &mut callees);
+
+    function.expressions.iter().any(|(_, expression)| {
+        matches!(expression, naga::Expression::GlobalVariable(handle) if *handle == global)
+    }) || callees
+        .into_iter()
+        // WGSL doesn't allow recursion, so this always ends.
+        .any(|callee| uses_global(module, &module.functions[callee], global))
+}
+
+/// Collects the functions called anywhere in `block`.
+fn calls(block: &naga::Block, callees: &mut Vec<Handle<naga::Function>>) {
+    for statement in block.iter() {
+        match statement {
+            naga::Statement::Call { function, .. } => callees.push(*function),
+            naga::Statement::Block(block) => calls(block, callees),
+            naga::Statement::If { accept, reject, .. } => {
+                calls(accept, callees);
+                calls(reject, callees);
+            }
+            naga::Statement::Switch { cases, .. } => {
+                for case in cases {
+                    calls(&case.body, callees);
+                }
+            }
+            naga::Statement::Loop {
+                body, continuing, ..
+            } => {
+                calls(body, callees);
+                calls(continuing, callees);
+            }
+            _ => {}
+        }
+    }
+}
+
+pub(crate) fn shader_stage(stage: naga::ShaderStage) -> wgpu::ShaderStages {
+    match stage {
+        naga::ShaderStage::Vertex => wgpu::ShaderStages::VERTEX,
+        naga::ShaderStage::Fragment => wgpu::ShaderStages::FRAGMENT,
+        naga::ShaderStage::Compute => wgpu::ShaderStages::COMPUTE,
+    }
+}
+
+/// How the textures and samplers of a module are used together by `textureSample*` and
+/// `textureLoad` calls.
+#[derive(Debug, Default)]
+struct ImageUsage {
+    /// Texture and sampler pairs passed to `textureSample*`.
+    sampled: HashSet<(Handle<GlobalVariable>, Handle<GlobalVariable>)>,
+    /// Textures passed to `textureLoad`.
+    loaded: HashSet<Handle<GlobalVariable>>,
+}
+
+impl ImageUsage {
+    fn new(module: &naga::Module) -> Self {
+        let mut usage = Self::default();
+        let functions = module
+            .functions
+            .iter()
+            .map(|(_, function)| function)
+            .chain(module.entry_points.iter().map(|entry| &entry.function));
+
+        for function in functions {
+            for (_, expression) in function.expressions.iter() {
+                match *expression {
+                    naga::Expression::ImageSample { image, sampler, .. } => {
+                        if let (Some(image), Some(sampler)) =
+                            (global_of(function, image), global_of(function, sampler))
+                        {
+                            usage.sampled.insert((image, sampler));
+                        }
+                    }
+                    naga::Expression::ImageLoad { image, .. } => {
+                        usage.loaded.extend(global_of(function, image));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        usage
+    }
+
+    /// Float textures that are only ever read with `textureLoad` can be unfilterable.
+    ///
+    /// Samplers keep their filtering type: a sampler only has to be non-filtering when it's
+    /// paired with an unfilterable float texture, and a texture passed to `textureSample*` is
+    /// always filterable here. Depth textures can be sampled with a filtering sampler.
+    fn refine(&self, global: Handle<GlobalVariable>, ty: wgpu::BindingType) -> wgpu::BindingType {
+        match ty {
+            wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { .. },
+                view_dimension,
+                multisampled,
+            } => wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float {
+                    filterable: !self.loaded.contains(&global)
+                        || self.sampled.iter().any(|&(texture, _)| texture == global),
+                },
+                view_dimension,
+                multisampled,
+            },
+            ty => ty,
+        }
+    }
+}
+
+/// The global variable an image or sampler expression refers to, looking through indexing
+/// into binding arrays.
+fn global_of(
+    function: &naga::Function,
+    expression: Handle<naga::Expression>,
+) -> Option<Handle<GlobalVariable>> {
+    match function.expressions[expression] {
+        naga::Expression::GlobalVariable(global) => Some(global),
+        naga::Expression::Access { base, .. } | naga::Expression::AccessIndex { base, .. } => {
+            global_of(function, base)
+        }
+        _ => None,
+    }
+}
+
+/// Splits a `binding_array` into its element type and length, other types having no length.
+fn binding_array(
+    module: &naga::Module,
+    ty: naga::Handle<naga::Type>,
+) -> Result<(naga::Handle<naga::Type>, Option<NonZeroU32>)> {
+    match module.types[ty].inner {
+        TypeInner::BindingArray {
+            base,
+            size: naga::ArraySize::Constant(count),
+        } => Ok((base, Some(count))),
+        TypeInner::BindingArray {
+            size: naga::ArraySize::Dynamic,
+            ..
+        } => bail!("binding arrays need a fixed length"),
+        _ => Ok((ty, None)),
+    }
+}
+
+/// The device features needed for the resources bound in the module.
+pub(crate) fn required_features(module: &naga::Module) -> wgpu::Features {
+    let non_uniform = non_uniformly_indexed(module);
+    module
+        .global_variables
+        .iter()
+        .filter_map(|(global, var)| match module.types[var.ty].inner {
+            TypeInner::BindingArray { base, .. } => {
+                Some((global, var.space, &module.types[base].inner))
+            }
+            _ => None,
+        })
+        .fold(
+            wgpu::Features::empty(),
+            |features, (global, space, base)| {
+                let (array, non_uniform_indexing) = match (space, base) {
+                (
+                    _,
+                    TypeInner::Image {
+                        class: naga::ImageClass::Storage { .. },
+                        ..
+                    },
+                ) => (
+                    wgpu::Features::TEXTURE_BINDING_ARRAY
+                        | wgpu::Features::STORAGE_RESOURCE_BINDING_ARRAY,
+                    wgpu::Features::UNIFORM_BUFFER_AND_STORAGE_TEXTURE_ARRAY_NON_UNIFORM_INDEXING,
+                ),
+                (naga::AddressSpace::Storage { .. }, _) => (
+                    wgpu::Features::BUFFER_BINDING_ARRAY
+                        | wgpu::Features::STORAGE_RESOURCE_BINDING_ARRAY,
+                    wgpu::Features::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING,
+                ),
+                (naga::AddressSpace::Uniform, _) => (
+                    wgpu::Features::BUFFER_BINDING_ARRAY,
+                    wgpu::Features::UNIFORM_BUFFER_AND_STORAGE_TEXTURE_ARRAY_NON_UNIFORM_INDEXING,
+                ),
+                // sampler arrays go with sampled textures.
+                _ => (
+                    wgpu::Features::TEXTURE_BINDING_ARRAY,
+                    wgpu::Features::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING,
+                ),
+            };
+                if non_uniform.contains(&global) {
+                    features | array | non_uniform_indexing
+                } else {
+                    features | array
+                }
+            },
+        )
+}
+
+/// Globals indexed by a value that can differ between invocations of a draw or dispatch.
+fn non_uniformly_indexed(module: &naga::Module) -> HashSet<Handle<GlobalVariable>> {
+    // only the analysis is needed, and without it every index has to be taken as non-uniform.
+    let info = Validator::new(ValidationFlags::empty(), Capabilities::all())
+        .validate(module)
+        .ok();
+    let functions = module
+        .functions
+        .iter()
+        .map(|(handle, function)| (function, info.as_ref().map(|info| &info[handle])))
+        .chain(
+            module
+                .entry_points
+                .iter()
+                .enumerate()
+                .map(|(index, entry_point)| {
+                    (
+                        &entry_point.function,
+                        info.as_ref().map(|info| info.get_entry_point(index)),
+                    )
+                }),
+        );
+
+    let mut globals = HashSet::new();
+    for (function, info) in functions {
+        for (_, expression) in function.expressions.iter() {
+            let naga::Expression::Access { base, index } = *expression else {
+                continue;
+            };
+            let naga::Expression::GlobalVariable(global) = function.expressions[base] else {
+                continue;
+            };
+            let uniform =
+                info.is_some_and(|info| info[index].uniformity.non_uniform_result.is_none());
+            if !uniform {
+                globals.insert(global);
+            }
+        }
+    }
+    globals
+}
+
+/// Maps a `var<uniform>` or `var<storage>` of `size` bytes to a buffer binding.
+fn map_address_space_to_wgpu_buffer_binding_type(
+    space: naga::AddressSpace,
+    size: u32,
+) -> Result<wgpu::BindingType> {
+    let ty = match space {
+        naga::AddressSpace::Uniform => wgpu::BufferBindingType::Uniform,
+        naga::AddressSpace::Storage { access } => wgpu::BufferBindingType::Storage {
+            read_only: !access.contains(naga::StorageAccess::STORE),
+        },
+        space => bail!("{space:?} is not a valid address space for a buffer binding"),
+    };
+    Ok(wgpu::BindingType::Buffer {
+        ty,
+        has_dynamic_offset: false,
+        min_binding_size: wgpu::BufferSize::new(size.into()),
+    })
+}
+
+fn map_naga_inner_type_to_wgpu_binding_type(ty: &naga::Type) -> Result<wgpu::BindingType> {
+    match ty.inner {
+        naga::TypeInner::Image {
+            dim,
+            arrayed,
+            class,
+        } => {
+            let view_dimension = map_naga_image_dimension(dim, arrayed)?;
+            match class {
+                naga::ImageClass::Sampled { kind, multi } => Ok(wgpu::BindingType::Texture {
+                    sample_type: match kind {
+                        naga::ScalarKind::Float => {
+                            wgpu::TextureSampleType::Float { filterable: true }
+                        }
+                        naga::ScalarKind::Uint => wgpu::TextureSampleType::Uint,
+                        naga::ScalarKind::Sint => wgpu::TextureSampleType::Sint,
+                        naga::ScalarKind::Bool => bail!("scalarkind not supported"),
+                    },
+                    view_dimension,
+                    multisampled: multi,
+                }),
+                naga::ImageClass::Depth { multi } => Ok(wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Depth,
+                    view_dimension,
+                    multisampled: multi,
+                }),
+                naga::ImageClass::Storage { format, access } => {
+                    Ok(wgpu::BindingType::StorageTexture {
+                        access: map_naga_storage_access(access)?,
+                        format: map_naga_storage_format(format),
+                        view_dimension,
+                    })
+                }
+            }
+        }
+        naga::TypeInner::Sampler { comparison } if comparison => Ok(wgpu::BindingType::Sampler(
+            wgpu::SamplerBindingType::Comparison,
+        )),
+        naga::TypeInner::Sampler { comparison } if !comparison => Ok(wgpu::BindingType::Sampler(
+            wgpu::SamplerBindingType::Filtering,
+        )),
+        _ => bail!("not a valid type for a binding resource"),
+    }
+}
+
+fn map_naga_image_dimension(
+    dim: naga::ImageDimension,
+    arrayed: bool,
+) -> Result<wgpu::TextureViewDimension> {
+    Ok(match (dim, arrayed) {
+        (naga::ImageDimension::D1, false) => wgpu::TextureViewDimension::D1,
+        (naga::ImageDimension::D2, false) => wgpu::TextureViewDimension::D2,
+        (naga::ImageDimension::D2, true) => wgpu::TextureViewDimension::D2Array,
+        (naga::ImageDimension::D3, false) => wgpu::TextureViewDimension::D3,
+        (naga::ImageDimension::Cube, false) => wgpu::TextureViewDimension::Cube,
+        (naga::ImageDimension::Cube, true) => wgpu::TextureViewDimension::CubeArray,
+        (dim, true) => bail!("{dim:?} textures can't be arrayed, use a binding_array instead"),
+    })
+}
+
+fn map_naga_storage_access(access: naga::StorageAccess) -> Result<wgpu::StorageTextureAccess> {
+    let load = access.contains(naga::StorageAccess::LOAD);
+    let store = access.contains(naga::StorageAccess::STORE);
+    Ok(match (load, store) {
+        (true, true) => wgpu::StorageTextureAccess::ReadWrite,
+        (true, false) => wgpu::StorageTextureAccess::ReadOnly,
+        (false, true) => wgpu::StorageTextureAccess::WriteOnly,
+        (false, false) => bail!("storage texture can neither be read nor written"),
+    })
+}
+
+fn map_naga_storage_format(format: naga::StorageFormat) -> wgpu::TextureFormat {
+    use naga::StorageFormat as S;
+    use wgpu::TextureFormat as T;
+
+    match format {
+        S::R8Unorm => T::R8Unorm,
+        S::R8Snorm => T::R8Snorm,
+        S::R8Uint => T::R8Uint,
+        S::R8Sint => T::R8Sint,
+        S::R16Uint => T::R16Uint,
+        S::R16Sint => T::R16Sint,
+        S::R16Float => T::R16Float,
+        S::R16Unorm => T::R16Unorm,
+        S::R16Snorm => T::R16Snorm,
+        S::Rg8Unorm => T::Rg8Unorm,
+        S::Rg8Snorm => T::Rg8Snorm,
+        S::Rg8Uint => T::Rg8Uint,
+        S::Rg8Sint => T::Rg8Sint,
+        S::R32Uint => T::R32Uint,
+        S::R32Sint => T::R32Sint,
+        S::R32Float => T::R32Float,
+        S::Rg16Uint => T::Rg16Uint,
+        S::Rg16Sint => T::Rg16Sint,
+        S::Rg16Float => T::Rg16Float,
+        S::Rg16Unorm => T::Rg16Unorm,
+        S::Rg16Snorm => T::Rg16Snorm,
+        S::Rgba8Unorm => T::Rgba8Unorm,
+        S::Rgba8Snorm => T::Rgba8Snorm,
+        S::Rgba8Uint => T::Rgba8Uint,
+        S::Rgba8Sint => T::Rgba8Sint,
+        S::Bgra8Unorm => T::Bgra8Unorm,
+        S::Rgb10a2Uint => T::Rgb10a2Uint,
+        S::Rgb10a2Unorm => T::Rgb10a2Unorm,
+        S::Rg11b10Float => T::Rg11b10Float,
+        S::Rg32Uint => T::Rg32Uint,
+        S::Rg32Sint => T::Rg32Sint,
+        S::Rg32Float => T::Rg32Float,
+        S::Rgba16Uint => T::Rgba16Uint,
+        S::Rgba16Sint => T::Rgba16Sint,
+        S::Rgba16Float => T::Rgba16Float,
+        S::Rgba16Unorm => T::Rgba16Unorm,
+        S::Rgba16Snorm => T::Rgba16Snorm,
+        S::Rgba32Uint => T::Rgba32Uint,
+        S::Rgba32Sint => T::Rgba32Sint,
+        S::Rgba32Float => T::Rgba32Float,
+    }
+}
@@ -0,0 +1,164 @@
+use std::{collections::HashMap, fs, io, path::Path};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::ShaderReflection;
+
+/// Shader reflection keyed by a hash of the WGSL source, so unchanged shaders don't have to be
+/// parsed and validated again.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReflectionCache {
+    version: u32,
+    entries: HashMap<u64, ShaderReflection>,
+}
+
+impl Default for ReflectionCache {
+    fn default() -> Self {
+        Self {
+            version: Self::VERSION,
+            entries: HashMap::new(),
+        }
+    }
+}
+
+impl ReflectionCache {
+    /// Bumped whenever [`ShaderReflection`] changes, so caches written before are thrown away.
+    pub const VERSION: u32 = 1;
+
+    /// Reads a cache written by [`save`](Self::save). A missing or invalid file, or one written
+    /// for another version, gives an empty cache.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        #[derive(Deserialize)]
+        struct Version {
+            version: u32,
+        }
+
+        let path = path.as_ref();
+        let json = match fs::read_to_string(path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => {
+                return Err(e).with_context(|| {
+                    format!("Could not read reflection cache '{}'", path.display())
+                })
+            }
+        };
+
+        // a cache cut short by a crash is as good as none.
+        match serde_json::from_str(&json) {
+            Ok(Version { version }) if version == Self::VERSION => {
+                Ok(serde_json::from_str(&json).unwrap_or_default())
+            }
+            _ => Ok(Self::default()),
+        }
+    }
+
+    /// Writes the cache to `path`, creating its directory if needed. The file is replaced
+    /// in one go, so a crash while saving leaves the previous one intact.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let context = || format!("Could not write reflection cache '{}'", path.display());
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).with_context(context)?;
+        }
+
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        fs::write(&temp, serde_json::to_string_pretty(self)?).with_context(context)?;
+        fs::rename(&temp, path).with_context(context)
+    }
+
+    pub fn get(&self, source: &str) -> Option<&ShaderReflection> {
+        self.entries.get(&source_hash(source))
+    }
+
+    pub fn insert(&mut self, source: &str, reflection: ShaderReflection) {
+        self.entries.insert(source_hash(source), reflection);
+    }
+
+    /// Keeps only the reflection of sources whose [`source_hash`] passes `keep`.
+    pub fn retain(&mut self, mut keep: impl FnMut(u64) -> bool) {
+        self.entries.retain(|&hash, _| keep(hash));
+    }
+
+    /// Gets the reflection of `source`, parsing and validating it only if it isn't cached yet.
+    pub fn reflect(&mut self, source: &str) -> Result<ShaderReflection> {
+        if let Some(reflection) = self.get(source) {
+            return Ok(reflection.clone());
+        }
+
+        let reflection = ShaderReflection::from_wgsl(source)?;
+        self.insert(source, reflection.clone());
+        Ok(reflection)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// A 64-bit FNV-1a hash of `source`. Unlike std's hasher it is the same on every platform and
+/// compiler version, so a cache can be shipped with a game.
+pub fn source_hash(source: &str) -> u64 {
+    source.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SOURCE: &str = include_str!("../../shaders/main.wgsl");
+
+    #[test]
+    fn hash() {
+        assert_eq!(source_hash(""), 0xcbf29ce484222325);
+        assert_eq!(source_hash("a"), 0xaf63dc4c8601ec8c);
+        assert_ne!(
+            source_hash(SOURCE),
+            source_hash(&SOURCE.replace("255.0", "256.0"))
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut cache = ReflectionCache::default();
+        let reflection = cache.reflect(SOURCE).unwrap();
+        assert_eq!(cache.get(SOURCE), Some(&reflection));
+        assert!(cache.reflect("not wgsl").is_err());
+        assert_eq!(cache.len(), 1);
+
+        let path = std::env::temp_dir().join(format!("purs-cache-{}.json", std::process::id()));
+        cache.save(&path).unwrap();
+        let loaded = ReflectionCache::load(&path).unwrap();
+        assert_eq!(loaded, cache);
+
+        fs::write(&path, r#"{ "version": 0, "entries": [] }"#).unwrap();
+        assert!(ReflectionCache::load(&path).unwrap().is_empty());
+        let json = serde_json::to_string(&cache).unwrap();
+        fs::write(&path, &json[..json.len() / 2]).unwrap();
+        assert!(ReflectionCache::load(&path).unwrap().is_empty());
+        fs::remove_file(&path).unwrap();
+
+        assert!(ReflectionCache::load(&path).unwrap().is_empty());
+
+        cache.retain(|hash| hash != source_hash(SOURCE));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn save_creates_directory() {
+        let dir = std::env::temp_dir().join(format!("purs-cache-dir-{}", std::process::id()));
+        let path = dir.join("shaders").join("reflection.json");
+        ReflectionCache::default().save(&path).unwrap();
+        assert!(ReflectionCache::load(&path).unwrap().is_empty());
+        assert!(!path.with_extension("json.tmp").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
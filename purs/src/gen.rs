@@ -1,8 +1,9 @@
+use std::{collections::HashSet, fmt::Write, fs, path::Path};
+
 use anyhow::*;
-use std::collections::HashMap;
-use wgpu::{
-    BindGroupLayoutEntry, BindingType, SamplerBindingType, ShaderStages, TextureViewDimension,
-};
+use naga::{ScalarKind, TypeInner};
+
+use crate::{EntryPoint, IoType, ShaderReflection, MAX_BIND_GROUPS};
 
 /// Generate a wgpu::PipelineLayout for the shader.
 pub fn generate_pipeline_layout(
@@ -16,143 +17,400 @@ pub fn generate_pipeline_layout(
     })
 }
 
+/// Generate a wgpu::BindGroupLayout for every bind group, including the empty ones.
 pub fn generate_bind_group_layouts(
     device: &wgpu::Device,
-    entries: HashMap<usize, Vec<wgpu::BindGroupLayoutEntry>>,
-) -> Vec<wgpu::BindGroupLayout> {
-    // Iterate over the entries collection, create bind group layouts, and collect them into a vector.
-    entries
-        .values()
-        .map(|entries| {
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: None,
-                entries,
-            })
+    reflection: &ShaderReflection,
+) -> [wgpu::BindGroupLayout; MAX_BIND_GROUPS] {
+    std::array::from_fn(|group| {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &reflection.layout_entries(group),
         })
-        .collect::<Vec<_>>()
+    })
 }
 
-pub fn generate_layout_entries(
-    module: &naga::Module,
-) -> Result<HashMap<usize, Vec<BindGroupLayoutEntry>>> {
-    module
-        .global_variables
+/// Checks the inputs of a vertex `entry_point` against `layout`, returning the attributes of
+/// `layout` the entry point reads.
+///
+/// Every `@location` the shader reads has to be provided by the layout with exactly the same
+/// scalar type and component count.
+pub fn vertex_attributes(
+    entry_point: &EntryPoint,
+    layout: &wgpu::VertexBufferLayout,
+) -> Result<Vec<wgpu::VertexAttribute>> {
+    entry_point
+        .inputs
         .iter()
-        .filter_map(|(handle, var)| var.binding.as_ref().map(|binding| (handle, var, binding)))
-        .try_fold(
-            HashMap::<usize, Vec<BindGroupLayoutEntry>>::new(),
-            |mut acc, (global_handle, var, binding)| {
-                let stages = module
-                    .entry_points
-                    .iter()
-                    .filter_map(|entry_point| {
-                        entry_point
-                            .function
-                            .expressions
-                            .iter()
-                            .find_map(|(_, expr)| match expr {
-                                naga::Expression::GlobalVariable(handle)
-                                    if *handle == global_handle =>
-                                {
-                                    Some(entry_point.stage)
-                                }
-                                _ => None,
-                            })
-                    })
-                    .fold(
-                        ShaderStages::NONE,
-                        |stages, shader_stage| match shader_stage {
-                            naga::ShaderStage::Vertex => stages | ShaderStages::VERTEX,
-                            naga::ShaderStage::Fragment => stages | ShaderStages::FRAGMENT,
-                            naga::ShaderStage::Compute => stages | ShaderStages::COMPUTE,
-                        },
-                    );
-
-                let ty = map_naga_inner_type_to_wgpu_binding_type(module.types.get_handle(var.ty)?)
-                    .map_err(|e| {
-                        anyhow!("Failed to map Naga inner type to WGPU binding type: {}", e)
-                    })?;
-
-                let group: usize = binding
-                    .group
-                    .try_into()
-                    .map_err(|e| anyhow!("Failed to convert binding group to usize: {}", e))?;
-
-                acc.entry(group).or_default().push(BindGroupLayoutEntry {
-                    binding: binding.binding,
-                    visibility: stages,
-                    ty,
-                    count: None,
-                });
-
-                Ok(acc)
-            },
-        )
+        .map(|input| {
+            let location = input.location;
+            let attribute = layout
+                .attributes
+                .iter()
+                .find(|attribute| attribute.shader_location == location)
+                .ok_or_else(|| {
+                    anyhow!(
+                        "vertex input @location({location}) of '{}' is not provided by the vertex type",
+                        entry_point.name
+                    )
+                })?;
+            let provided = IoType::from_vertex_format(attribute.format);
+            ensure!(
+                provided == input.ty,
+                "vertex input @location({location}) of '{}' is {}, but the vertex type provides {provided} ({:?})",
+                entry_point.name,
+                input.ty,
+                attribute.format
+            );
+            Ok(*attribute)
+        })
+        .collect()
+}
+
+/// Generate tightly packed vertex attributes for the inputs of a vertex entry point, ordered by
+/// location, along with the stride of the buffer.
+pub fn packed_vertex_attributes(
+    entry_point: &EntryPoint,
+) -> Result<(wgpu::BufferAddress, Vec<wgpu::VertexAttribute>)> {
+    let mut inputs = entry_point.inputs.iter().collect::<Vec<_>>();
+    inputs.sort_by_key(|input| input.location);
+
+    let mut offset = 0;
+    let attributes = inputs
+        .into_iter()
+        .map(|input| {
+            let format = input.ty.vertex_format()?;
+            let attribute = wgpu::VertexAttribute {
+                format,
+                offset,
+                shader_location: input.location,
+            };
+            offset += format.size();
+            Ok(attribute)
+        })
+        .collect::<Result<_>>()?;
+    Ok((offset, attributes))
+}
+
+/// Reads the WGSL shader at `wgsl_path` and writes the Rust structs [`structs`] generates for it
+/// to `out_path`. Meant to be called from a build script:
+///
+/// ```ignore
+/// let out_dir = std::path::PathBuf::from(std::env::var("OUT_DIR").unwrap());
+/// purs::gen::write_structs("shaders/main.wgsl", out_dir.join("main_shader.rs")).unwrap();
+/// println!("cargo:rerun-if-changed=shaders/main.wgsl");
+/// ```
+///
+/// and then `include!(concat!(env!("OUT_DIR"), "/main_shader.rs"));` where the structs are used.
+pub fn write_structs(wgsl_path: impl AsRef<Path>, out_path: impl AsRef<Path>) -> Result<()> {
+    let wgsl_path = wgsl_path.as_ref();
+    let source = fs::read_to_string(wgsl_path)
+        .with_context(|| format!("Could not read shader '{}'", wgsl_path.display()))?;
+    let module =
+        naga::front::wgsl::parse_str(&source).map_err(|e| anyhow!(e.emit_to_string(&source)))?;
+    let structs = structs(&module)
+        .with_context(|| format!("Could not generate structs for '{}'", wgsl_path.display()))?;
+
+    let out_path = out_path.as_ref();
+    fs::write(out_path, structs)
+        .with_context(|| format!("Could not write '{}'", out_path.display()))
+}
+
+/// Generate a `#[repr(C)]` [`bytemuck::Pod`] struct for every struct a uniform or storage
+/// buffer of `module` holds, including the structs those contain.
+///
+/// Padding the layouter puts between members becomes explicit `_padN` byte arrays, so the Rust
+/// struct has exactly the size and member offsets the shader expects. A runtime-sized array at
+/// the end of a storage buffer is left out, its elements are the struct of the array instead.
+///
+/// [`bytemuck::Pod`]: https://docs.rs/bytemuck/latest/bytemuck/trait.Pod.html
+pub fn structs(module: &naga::Module) -> Result<String> {
+    let mut layouter = naga::proc::Layouter::default();
+    layouter.update(module.to_ctx())?;
+
+    let mut used = HashSet::new();
+    for (_, global) in module.global_variables.iter() {
+        if matches!(
+            global.space,
+            naga::AddressSpace::Uniform | naga::AddressSpace::Storage { .. }
+        ) {
+            collect_structs(module, global.ty, &mut used);
+        }
+    }
+
+    let mut out = String::from("// Generated by purs from WGSL, do not edit.\n");
+    // types come after the types they are built from, so this is also a valid declaration order.
+    for (handle, ty) in module.types.iter() {
+        if !used.contains(&handle) {
+            continue;
+        }
+        let TypeInner::Struct { members, span } = &ty.inner else {
+            unreachable!()
+        };
+        let name = ty
+            .name
+            .as_deref()
+            .ok_or_else(|| anyhow!("struct {handle:?} has no name"))?;
+
+        writeln!(out)?;
+        writeln!(out, "#[repr(C)]")?;
+        writeln!(
+            out,
+            "#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]"
+        )?;
+        writeln!(out, "pub struct {name} {{")?;
+
+        let mut offset = 0;
+        let mut end = *span;
+        let mut padding = 0;
+        let mut pad = |out: &mut String, to: u32, offset: &mut u32| -> Result<()> {
+            if to > *offset {
+                writeln!(out, "    pub _pad{padding}: [u8; {}],", to - *offset)?;
+                padding += 1;
+                *offset = to;
+            }
+            Ok(())
+        };
+        for member in members {
+            let member_name = member.name.as_deref().unwrap_or("_");
+            if let TypeInner::Array {
+                size: naga::ArraySize::Dynamic,
+                ..
+            } = module.types[member.ty].inner
+            {
+                end = member.offset;
+                pad(&mut out, end, &mut offset)?;
+                writeln!(
+                    out,
+                    "    // `{member_name}` is a runtime-sized array, which starts here."
+                )?;
+                break;
+            }
+
+            pad(&mut out, member.offset, &mut offset)?;
+            let (rust_type, size) = rust_type(module, &layouter, member.ty)
+                .with_context(|| format!("member '{member_name}' of struct '{name}'"))?;
+            writeln!(out, "    pub {}: {rust_type},", field_name(member_name))?;
+            offset += size;
+        }
+        pad(&mut out, end, &mut offset)?;
+
+        writeln!(out, "}}")?;
+        writeln!(
+            out,
+            "const _: () = assert!(std::mem::size_of::<{name}>() == {offset});"
+        )?;
+    }
+    Ok(out)
 }
 
-fn vertex_attributes(ty: &naga::Type) -> Result<()> {
-    match &ty.inner {
-        naga::TypeInner::Struct { members, span } => unimplemented!(""),
-        _ => bail!("not a struct!"),
+/// Adds `ty` and every struct it contains to `structs`, if it is a struct.
+fn collect_structs(
+    module: &naga::Module,
+    ty: naga::Handle<naga::Type>,
+    structs: &mut HashSet<naga::Handle<naga::Type>>,
+) {
+    match module.types[ty].inner {
+        // structs already collected have had their members collected too.
+        TypeInner::Struct { ref members, .. } if structs.insert(ty) => {
+            for member in members {
+                collect_structs(module, member.ty, structs);
+            }
+        }
+        TypeInner::Array { base, .. } | TypeInner::BindingArray { base, .. } => {
+            collect_structs(module, base, structs)
+        }
+        _ => {}
     }
 }
 
-fn map_naga_inner_type_to_wgpu_binding_type(ty: &naga::Type) -> Result<BindingType> {
-    match ty.inner {
-        naga::TypeInner::Image {
-            dim,
-            arrayed,
-            class,
-        } => match class {
-            naga::ImageClass::Sampled { kind, multi } => match kind {
-                naga::ScalarKind::Float => Ok(wgpu::TextureSampleType::Float { filterable: true }),
-                naga::ScalarKind::Uint => Ok(wgpu::TextureSampleType::Uint),
-                naga::ScalarKind::Sint => Ok(wgpu::TextureSampleType::Sint),
-                naga::ScalarKind::Bool => Ok(wgpu::TextureSampleType::Depth),
+/// The Rust type for a host-shareable WGSL type, along with its size in bytes.
+fn rust_type(
+    module: &naga::Module,
+    layouter: &naga::proc::Layouter,
+    ty: naga::Handle<naga::Type>,
+) -> Result<(String, u32)> {
+    let scalar = |kind, width| -> Result<&str> {
+        Ok(match (kind, width) {
+            (ScalarKind::Float, 4) => "f32",
+            (ScalarKind::Float, 8) => "f64",
+            (ScalarKind::Uint, 4) => "u32",
+            (ScalarKind::Sint, 4) => "i32",
+            _ => bail!(
+                "{} can't be shared with the CPU",
+                IoType {
+                    kind,
+                    width,
+                    components: 1
+                }
+            ),
+        })
+    };
+
+    let size = layouter[ty].size;
+    let rust_type = match module.types[ty].inner {
+        TypeInner::Scalar { kind, width } | TypeInner::Atomic { kind, width } => {
+            scalar(kind, width)?.to_owned()
+        }
+        TypeInner::Vector { size, kind, width } => {
+            let prefix = match (kind, width) {
+                (ScalarKind::Float, 4) => "",
+                (ScalarKind::Float, 8) => "D",
+                (ScalarKind::Uint, 4) => "U",
+                (ScalarKind::Sint, 4) => "I",
+                _ => bail!(
+                    "{} can't be shared with the CPU",
+                    IoType {
+                        kind,
+                        width,
+                        components: size as u8
+                    }
+                ),
+            };
+            format!("glam::{prefix}Vec{}", size as u8)
+        }
+        TypeInner::Matrix {
+            columns,
+            rows,
+            width,
+        } => {
+            let (columns, rows) = (columns as u8, rows as u8);
+            let scalar = scalar(ScalarKind::Float, width)?;
+            match (columns, rows, width) {
+                (4, 4, 4) => "glam::Mat4".to_owned(),
+                (4, 4, 8) => "glam::DMat4".to_owned(),
+                // columns of vec3 are padded to vec4.
+                (columns, 2, _) => format!("[[{scalar}; 2]; {columns}]"),
+                (columns, _, _) => format!("[[{scalar}; 4]; {columns}]"),
             }
-            .and_then(|sample_type| match dim {
-                naga::ImageDimension::D1 if !arrayed => Ok(BindingType::Texture {
-                    sample_type,
-                    view_dimension: TextureViewDimension::D1,
-                    multisampled: multi,
-                }),
-                naga::ImageDimension::D2 if !arrayed => Ok(BindingType::Texture {
-                    sample_type,
-                    view_dimension: TextureViewDimension::D2,
-                    multisampled: multi,
-                }),
-                naga::ImageDimension::D3 if !arrayed => Ok(BindingType::Texture {
-                    sample_type,
-                    view_dimension: TextureViewDimension::D3,
-                    multisampled: multi,
-                }),
-                naga::ImageDimension::Cube if !arrayed => Ok(BindingType::Texture {
-                    sample_type,
-                    view_dimension: TextureViewDimension::Cube,
-                    multisampled: multi,
-                }),
-                naga::ImageDimension::D2 if arrayed => Ok(BindingType::Texture {
-                    sample_type,
-                    view_dimension: TextureViewDimension::D2Array,
-                    multisampled: multi,
-                }),
-                naga::ImageDimension::Cube if arrayed => Ok(BindingType::Texture {
-                    sample_type,
-                    view_dimension: TextureViewDimension::CubeArray,
-                    multisampled: multi,
-                }),
-                _ => bail!("not a supported texture type"),
-            }),
-            naga::ImageClass::Depth { .. } => bail!("not implemented"),
-            naga::ImageClass::Storage { .. } => bail!("not implemented"),
-            _ => bail!("scalarkind not supported"),
-        },
-        naga::TypeInner::Sampler { comparison } if comparison => {
-            Ok(BindingType::Sampler(SamplerBindingType::Comparison))
         }
-        naga::TypeInner::Sampler { comparison } if !comparison => {
-            Ok(BindingType::Sampler(SamplerBindingType::Filtering))
+        TypeInner::Array {
+            base,
+            size: naga::ArraySize::Constant(count),
+            stride,
+        } => {
+            let (base, base_size) = rust_type(module, layouter, base)?;
+            ensure!(
+                base_size == stride,
+                "array<{base}> elements are {stride} bytes apart, but {base} is {base_size} bytes"
+            );
+            format!("[{base}; {count}]")
         }
-        _ => bail!("not a valid type for a binding resource"),
+        TypeInner::Struct { .. } => module.types[ty]
+            .name
+            .clone()
+            .ok_or_else(|| anyhow!("struct {ty:?} has no name"))?,
+        ref inner => bail!("{inner:?} can't be shared with the CPU"),
+    };
+    Ok((rust_type, size))
+}
+
+/// `name`, escaped if it is a Rust keyword.
+fn field_name(name: &str) -> String {
+    const KEYWORDS: &[&str] = &[
+        "as", "async", "await", "box", "break", "const", "continue", "crate", "dyn", "else",
+        "enum", "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod",
+        "move", "mut", "pub", "ref", "return", "static", "struct", "trait", "true", "type",
+        "unsafe", "use", "where", "while", "yield",
+    ];
+    if KEYWORDS.contains(&name) {
+        format!("r#{name}")
+    } else {
+        name.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn uniform_structs() {
+        let module = naga::front::wgsl::parse_str(
+            "
+            struct Light {
+                position: vec3<f32>,
+                intensity: f32,
+                color: vec3<f32>,
+            }
+
+            struct Camera {
+                view: mat4x4<f32>,
+                normal: mat3x3<f32>,
+                lights: array<Light, 4>,
+                count: u32,
+            }
+
+            struct Particles {
+                time: f32,
+                particles: array<vec4<f32>>,
+            }
+
+            struct Unused {
+                x: f32,
+            }
+
+            @group(0) @binding(0)
+            var<uniform> camera: Camera;
+            @group(0) @binding(1)
+            var<storage, read_write> particles: Particles;
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            structs(&module).unwrap(),
+            "// Generated by purs from WGSL, do not edit.
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Light {
+    pub position: glam::Vec3,
+    pub intensity: f32,
+    pub color: glam::Vec3,
+    pub _pad0: [u8; 4],
+}
+const _: () = assert!(std::mem::size_of::<Light>() == 32);
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Camera {
+    pub view: glam::Mat4,
+    pub normal: [[f32; 4]; 3],
+    pub lights: [Light; 4],
+    pub count: u32,
+    pub _pad0: [u8; 12],
+}
+const _: () = assert!(std::mem::size_of::<Camera>() == 256);
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Particles {
+    pub time: f32,
+    pub _pad0: [u8; 12],
+    // `particles` is a runtime-sized array, which starts here.
+}
+const _: () = assert!(std::mem::size_of::<Particles>() == 16);
+"
+        );
+    }
+
+    #[test]
+    fn padded_arrays() {
+        let module = naga::front::wgsl::parse_str(
+            "
+            struct Data {
+                values: array<vec3<f32>, 2>,
+            }
+
+            @group(0) @binding(0)
+            var<storage> data: Data;
+            ",
+        )
+        .unwrap();
+        assert_eq!(
+            format!("{:#}", structs(&module).unwrap_err()),
+            "member 'values' of struct 'Data': array<glam::Vec3> elements are 16 bytes apart, but glam::Vec3 is 12 bytes"
+        );
     }
 }
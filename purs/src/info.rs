@@ -29,77 +29,240 @@ pub fn query_attachments(module: &naga::Module) -> Result<usize> {
 }
 
 pub fn query_types(module: &naga::Module) {
-    module.types.iter().for_each(|(_handle, ty)| {
-        if let Some(name) = &ty.name {
-            println!("Struct {name}");
-            if let naga::TypeInner::Struct { members, .. } = &ty.inner {
-                members.iter().for_each(|member| {
-                    if let Some(name) = &member.name {
-                        println!(
-                            "\t{name}: {}",
-                            type_string(&module.types[member.ty].inner)
-                                .unwrap_or("unable to parse type")
-                        );
-                        if let Some(binding) = &member.binding {
-                            match binding {
-                                naga::Binding::Location { location, .. } => {
-                                    println!("\tbinding: {location}")
-                                }
-                                naga::Binding::BuiltIn(builtin) => {
-                                    println!("\tbuiltin: {builtin:?}")
-                                }
-                            }
+    module.types.iter().for_each(|(handle, ty)| {
+        if let naga::TypeInner::Struct { members, .. } = &ty.inner {
+            println!("Struct {}", type_string(module, handle));
+            members.iter().for_each(|member| {
+                println!(
+                    "\t{}: {}",
+                    member.name.as_deref().unwrap_or("_"),
+                    type_string(module, member.ty)
+                );
+                if let Some(binding) = &member.binding {
+                    match binding {
+                        naga::Binding::Location { location, .. } => {
+                            println!("\tbinding: {location}")
+                        }
+                        naga::Binding::BuiltIn(builtin) => {
+                            println!("\tbuiltin: {builtin:?}")
                         }
                     }
-                })
-            }
+                }
+            })
         } else {
-            println!(
-                "{}",
-                type_string(&ty.inner).unwrap_or("unable to parse type")
-            );
+            println!("{}", type_string(module, handle));
         }
     })
 }
 
-fn type_string(inner: &naga::TypeInner) -> Option<&str> {
-    match inner {
-        naga::TypeInner::Vector { size, kind, .. } => match (size, kind) {
-            (naga::VectorSize::Bi, naga::ScalarKind::Float) => Some("vec2<f32>"),
-            (naga::VectorSize::Tri, naga::ScalarKind::Float) => Some("vec3<f32>"),
-            (naga::VectorSize::Quad, naga::ScalarKind::Float) => Some("vec4<f32>"),
-            (naga::VectorSize::Bi, naga::ScalarKind::Uint) => Some("vec2<u32>"),
-            (naga::VectorSize::Tri, naga::ScalarKind::Uint) => Some("vec3<u32>"),
-            (naga::VectorSize::Quad, naga::ScalarKind::Uint) => Some("vec4<u32>"),
-            _ => unimplemented!(),
+/// The WGSL spelling of `ty`. Structs are called by their name.
+pub fn type_string(module: &naga::Module, ty: naga::Handle<naga::Type>) -> String {
+    let ty = &module.types[ty];
+    match (&ty.name, &ty.inner) {
+        (Some(name), naga::TypeInner::Struct { .. }) => name.clone(),
+        (_, inner) => inner_type_string(module, inner),
+    }
+}
+
+fn inner_type_string(module: &naga::Module, inner: &naga::TypeInner) -> String {
+    use naga::TypeInner as T;
+
+    match *inner {
+        T::Scalar { kind, width } => scalar_string(kind, width).to_owned(),
+        T::Vector { size, kind, width } => {
+            format!("vec{}<{}>", size as u8, scalar_string(kind, width))
+        }
+        T::Matrix {
+            columns,
+            rows,
+            width,
+        } => format!(
+            "mat{}x{}<{}>",
+            columns as u8,
+            rows as u8,
+            scalar_string(naga::ScalarKind::Float, width)
+        ),
+        T::Atomic { kind, width } => format!("atomic<{}>", scalar_string(kind, width)),
+        T::Pointer { base, space } => format!(
+            "ptr<{}, {}>",
+            address_space_string(space),
+            type_string(module, base)
+        ),
+        T::ValuePointer {
+            size,
+            kind,
+            width,
+            space,
+        } => {
+            let base = match size {
+                Some(size) => format!("vec{}<{}>", size as u8, scalar_string(kind, width)),
+                None => scalar_string(kind, width).to_owned(),
+            };
+            format!("ptr<{}, {base}>", address_space_string(space))
+        }
+        T::Array { base, size, .. } => match size {
+            naga::ArraySize::Constant(size) => {
+                format!("array<{}, {size}>", type_string(module, base))
+            }
+            naga::ArraySize::Dynamic => format!("array<{}>", type_string(module, base)),
+        },
+        T::BindingArray { base, size } => match size {
+            naga::ArraySize::Constant(size) => {
+                format!("binding_array<{}, {size}>", type_string(module, base))
+            }
+            naga::ArraySize::Dynamic => format!("binding_array<{}>", type_string(module, base)),
         },
-        naga::TypeInner::Image {
+        T::Struct { ref members, .. } => format!(
+            "struct {{ {} }}",
+            members
+                .iter()
+                .map(|member| format!(
+                    "{}: {}",
+                    member.name.as_deref().unwrap_or("_"),
+                    type_string(module, member.ty)
+                ))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        T::Image {
             dim,
             arrayed,
             class,
-        } => match class {
-            naga::ImageClass::Sampled {
-                kind: naga::ScalarKind::Float,
-                ..
-            } => match dim {
-                naga::ImageDimension::D2 => {
-                    if !arrayed {
-                        Some("2D Texture, Sampled Float")
+        } => {
+            let dim = match dim {
+                naga::ImageDimension::D1 => "1d",
+                naga::ImageDimension::D2 => "2d",
+                naga::ImageDimension::D3 => "3d",
+                naga::ImageDimension::Cube => "cube",
+            };
+            let arrayed = if arrayed { "_array" } else { "" };
+            match class {
+                naga::ImageClass::Sampled { kind, multi } => {
+                    let multi = if multi { "multisampled_" } else { "" };
+                    format!("texture_{multi}{dim}{arrayed}<{}>", scalar_string(kind, 4))
+                }
+                naga::ImageClass::Depth { multi } => {
+                    let multi = if multi { "multisampled_" } else { "" };
+                    format!("texture_depth_{multi}{dim}{arrayed}")
+                }
+                naga::ImageClass::Storage { format, access } => {
+                    let access = if access
+                        .contains(naga::StorageAccess::LOAD | naga::StorageAccess::STORE)
+                    {
+                        "read_write"
+                    } else if access.contains(naga::StorageAccess::STORE) {
+                        "write"
                     } else {
-                        None
-                    }
+                        "read"
+                    };
+                    format!(
+                        "texture_storage_{dim}{arrayed}<{}, {access}>",
+                        format!("{format:?}").to_lowercase()
+                    )
                 }
-                _ => unimplemented!(),
-            },
-            _ => unimplemented!(),
-        },
-        naga::TypeInner::Sampler { comparison } => {
-            if !comparison {
-                Some("Sampler, comparison: false")
+            }
+        }
+        T::Sampler { comparison: false } => "sampler".to_owned(),
+        T::Sampler { comparison: true } => "sampler_comparison".to_owned(),
+        T::AccelerationStructure => "acceleration_structure".to_owned(),
+        T::RayQuery => "ray_query".to_owned(),
+    }
+}
+
+fn scalar_string(kind: naga::ScalarKind, width: naga::Bytes) -> &'static str {
+    match (kind, width) {
+        (naga::ScalarKind::Sint, 8) => "i64",
+        (naga::ScalarKind::Sint, _) => "i32",
+        (naga::ScalarKind::Uint, 8) => "u64",
+        (naga::ScalarKind::Uint, _) => "u32",
+        (naga::ScalarKind::Float, 2) => "f16",
+        (naga::ScalarKind::Float, 8) => "f64",
+        (naga::ScalarKind::Float, _) => "f32",
+        (naga::ScalarKind::Bool, _) => "bool",
+    }
+}
+
+fn address_space_string(space: naga::AddressSpace) -> &'static str {
+    match space {
+        naga::AddressSpace::Function => "function",
+        naga::AddressSpace::Private => "private",
+        naga::AddressSpace::WorkGroup => "workgroup",
+        naga::AddressSpace::Uniform => "uniform",
+        naga::AddressSpace::Storage { access } => {
+            if access.contains(naga::StorageAccess::STORE) {
+                "storage, read_write"
             } else {
-                Some("Sampler, comparison: true")
+                "storage, read"
             }
         }
-        _ => None,
+        naga::AddressSpace::Handle => "handle",
+        naga::AddressSpace::PushConstant => "push_constant",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn type_strings() {
+        let module = naga::front::wgsl::parse_str(
+            "
+            struct Light {
+                position: vec3<f32>,
+                flags: atomic<u32>,
+            }
+
+            @group(0) @binding(0) var<storage, read_write> lights: array<Light>;
+            @group(0) @binding(1) var<uniform> normal: mat3x3<f32>;
+            @group(0) @binding(2) var<uniform> grid: array<vec4<i32>, 4>;
+            @group(1) @binding(0) var color: texture_2d_array<u32>;
+            @group(1) @binding(1) var msaa: texture_multisampled_2d<f32>;
+            @group(1) @binding(2) var shadow: texture_depth_cube_array;
+            @group(1) @binding(3) var output: texture_storage_3d<rgba16float, write>;
+            @group(1) @binding(4) var atlases: binding_array<texture_1d<i32>, 2>;
+            @group(1) @binding(5) var shadow_sampler: sampler_comparison;
+
+            fn read(p: ptr<function, bool>) -> bool {
+                return *p;
+            }
+            ",
+        )
+        .unwrap();
+
+        let global = |name: &str| {
+            let (_, global) = module
+                .global_variables
+                .iter()
+                .find(|(_, global)| global.name.as_deref() == Some(name))
+                .unwrap();
+            type_string(&module, global.ty)
+        };
+        assert_eq!(global("lights"), "array<Light>");
+        assert_eq!(global("normal"), "mat3x3<f32>");
+        assert_eq!(global("grid"), "array<vec4<i32>, 4>");
+        assert_eq!(global("color"), "texture_2d_array<u32>");
+        assert_eq!(global("msaa"), "texture_multisampled_2d<f32>");
+        assert_eq!(global("shadow"), "texture_depth_cube_array");
+        assert_eq!(global("output"), "texture_storage_3d<rgba16float, write>");
+        assert_eq!(global("atlases"), "binding_array<texture_1d<i32>, 2>");
+        assert_eq!(global("shadow_sampler"), "sampler_comparison");
+
+        let (_, function) = module.functions.iter().next().unwrap();
+        assert_eq!(
+            type_string(&module, function.arguments[0].ty),
+            "ptr<function, bool>"
+        );
+
+        let (light, _) = module
+            .types
+            .iter()
+            .find(|(_, ty)| ty.name.as_deref() == Some("Light"))
+            .unwrap();
+        assert_eq!(type_string(&module, light), "Light");
+        assert_eq!(
+            inner_type_string(&module, &module.types[light].inner),
+            "struct { position: vec3<f32>, flags: atomic<u32> }"
+        );
     }
 }
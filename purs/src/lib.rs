@@ -1,18 +1,25 @@
-mod gen;
+mod bindings;
+mod cache;
+pub mod gen;
 mod info;
-use anyhow::Result;
+mod reflect;
+mod report;
+pub use cache::*;
 pub use info::*;
-
-pub fn parse() -> Result<()> {
-    Ok(())
-}
+pub use reflect::*;
+pub use report::*;
 
 #[cfg(test)]
 mod test {
-    use crate::query_types;
+    use crate::{query_types, ShaderReflection};
 
     use super::query_attachments;
 
+    fn main_shader() -> ShaderReflection {
+        let module = naga::front::wgsl::parse_str(include_str!("../../shaders/main.wgsl")).unwrap();
+        ShaderReflection::new(&module).unwrap()
+    }
+
     #[test]
     fn attachment() {
         let module = naga::front::wgsl::parse_str(include_str!("../../shaders/main.wgsl")).unwrap();
@@ -20,4 +27,54 @@ mod test {
         println!("{module:#?}");
         assert_eq!(1, query_attachments(&module).unwrap())
     }
+
+    #[test]
+    fn uniform() {
+        let entries = main_shader().layout_entries(1);
+        assert_eq!(
+            entries[0].ty,
+            wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: wgpu::BufferSize::new(64),
+            }
+        );
+    }
+
+    #[test]
+    fn vertex_attributes() {
+        let reflection = main_shader();
+        let vertex = reflection
+            .entry_point(naga::ShaderStage::Vertex, None)
+            .unwrap();
+
+        let (stride, attributes) = crate::gen::packed_vertex_attributes(vertex).unwrap();
+        assert_eq!(stride, 32);
+        assert_eq!(
+            attributes,
+            wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Uint32x4]
+        );
+
+        // the engine packs vertex colours as bytes.
+        let layout = wgpu::VertexBufferLayout {
+            array_stride: 20,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Uint8x4],
+        };
+        assert_eq!(
+            crate::gen::vertex_attributes(vertex, &layout).unwrap(),
+            layout.attributes
+        );
+
+        let layout = wgpu::VertexBufferLayout {
+            attributes: &wgpu::vertex_attr_array![0 => Float32x2, 1 => Uint8x4, 2 => Float32x2],
+            ..layout
+        };
+        assert_eq!(
+            crate::gen::vertex_attributes(vertex, &layout)
+                .unwrap_err()
+                .to_string(),
+            "vertex input @location(1) of 'vertex' is vec2<f32>, but the vertex type provides vec4<u32> (Uint8x4)"
+        );
+    }
 }
@@ -0,0 +1,67 @@
+use std::{fs, process::ExitCode};
+
+use purs::Report;
+
+const USAGE: &str = "usage: purs [--json] <shader.wgsl>...
+
+Prints the entry points, bindings and struct layouts of each shader, and checks that the
+engine can build a pipeline from it. Exits with an error if any shader can't be used.
+
+    --json    print the reports as JSON instead";
+
+fn main() -> ExitCode {
+    let mut json = false;
+    let mut paths = vec![];
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--json" => json = true,
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return ExitCode::SUCCESS;
+            }
+            flag if flag.starts_with('-') => {
+                eprintln!("unknown option '{flag}'\n\n{USAGE}");
+                return ExitCode::from(2);
+            }
+            _ => paths.push(arg),
+        }
+    }
+    if paths.is_empty() {
+        eprintln!("{USAGE}");
+        return ExitCode::from(2);
+    }
+
+    let reports = paths
+        .into_iter()
+        .map(|path| match fs::read_to_string(&path) {
+            Ok(source) => Report::new(path, &source),
+            Err(e) => Report {
+                path,
+                reflection: None,
+                structs: vec![],
+                errors: vec![format!("Could not read shader: {e}")],
+                warnings: vec![],
+            },
+        })
+        .collect::<Vec<_>>();
+
+    if json {
+        match serde_json::to_string_pretty(&reports) {
+            Ok(json) => println!("{json}"),
+            Err(e) => {
+                eprintln!("{e}");
+                return ExitCode::FAILURE;
+            }
+        }
+    } else {
+        for report in &reports {
+            println!("{report}");
+        }
+    }
+
+    if reports.iter().all(Report::is_ok) {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
@@ -0,0 +1,702 @@
+use std::fmt;
+
+use anyhow::{anyhow, bail, Result};
+use naga::{
+    valid::{Capabilities, ValidationFlags, Validator},
+    TypeInner,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::bindings::{reflect_bindings, required_features, shader_stage};
+
+/// Bind groups a shader can use, matching wgpu's default `max_bind_groups`.
+pub const MAX_BIND_GROUPS: usize = 4;
+
+/// What a shader module takes in and puts out, as needed to build pipelines for it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShaderReflection {
+    pub entry_points: Vec<EntryPoint>,
+    /// Bindings of each bind group, in the order they are declared.
+    pub bind_groups: [Vec<Binding>; MAX_BIND_GROUPS],
+    /// Device features the bindings need.
+    pub features: wgpu::Features,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EntryPoint {
+    pub name: String,
+    pub stage: naga::ShaderStage,
+    /// `@location` inputs, which are the vertex attributes of a vertex entry point.
+    pub inputs: Vec<StageIo>,
+    /// `@location` outputs, which are the color targets of a fragment entry point.
+    pub outputs: Vec<StageIo>,
+    /// `@workgroup_size` of a compute entry point, `[0, 0, 0]` for the other stages.
+    pub workgroup_size: [u32; 3],
+}
+
+/// A user-defined `@location` input or output of an entry point.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StageIo {
+    pub name: Option<String>,
+    pub location: u32,
+    pub ty: IoType,
+}
+
+/// A scalar or vector, the only types that can pass between stages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct IoType {
+    pub kind: naga::ScalarKind,
+    pub width: naga::Bytes,
+    pub components: u8,
+}
+
+/// A resource bound to a group.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Binding {
+    pub name: Option<String>,
+    pub entry: wgpu::BindGroupLayoutEntry,
+}
+
+impl ShaderReflection {
+    /// Parses, validates and reflects WGSL source.
+    pub fn from_wgsl(source: &str) -> Result<Self> {
+        let module =
+            naga::front::wgsl::parse_str(source).map_err(|e| anyhow!(e.emit_to_string(source)))?;
+        Validator::new(ValidationFlags::all(), Capabilities::all())
+            .validate(&module)
+            .map_err(|e| anyhow!(e.emit_to_string(source)))?;
+        Self::new(&module)
+    }
+
+    /// Reflects a module, which should already have been validated.
+    pub fn new(module: &naga::Module) -> Result<Self> {
+        let entry_points = module
+            .entry_points
+            .iter()
+            .map(|entry_point| {
+                let function = &entry_point.function;
+                let inputs = function
+                    .arguments
+                    .iter()
+                    .map(|argument| (argument.name.as_ref(), argument.ty, &argument.binding));
+                let outputs = function
+                    .result
+                    .iter()
+                    .map(|result| (None, result.ty, &result.binding));
+
+                Ok(EntryPoint {
+                    name: entry_point.name.clone(),
+                    stage: entry_point.stage,
+                    inputs: stage_io(module, inputs)
+                        .map_err(|e| anyhow!("input of '{}' {e}", entry_point.name))?,
+                    outputs: stage_io(module, outputs)
+                        .map_err(|e| anyhow!("output of '{}' {e}", entry_point.name))?,
+                    workgroup_size: entry_point.workgroup_size,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            entry_points,
+            bind_groups: reflect_bindings(module)?,
+            features: required_features(module),
+        })
+    }
+
+    /// Binds the buffers at `bindings` with a dynamic offset.
+    pub fn set_dynamic_offsets(&mut self, bindings: &[naga::ResourceBinding]) -> Result<()> {
+        for resource in bindings {
+            let binding = self
+                .bind_groups
+                .get_mut(resource.group as usize)
+                .and_then(|group| {
+                    group
+                        .iter_mut()
+                        .find(|binding| binding.entry.binding == resource.binding)
+                })
+                .ok_or_else(|| {
+                    anyhow!(
+                        "dynamic offset requested for @group({}) @binding({}), which the shader doesn't have",
+                        resource.group,
+                        resource.binding
+                    )
+                })?;
+
+            match &mut binding.entry.ty {
+                wgpu::BindingType::Buffer {
+                    has_dynamic_offset, ..
+                } => *has_dynamic_offset = true,
+                _ => bail!(
+                    "dynamic offset requested for @group({}) @binding({}), which is not a buffer",
+                    resource.group,
+                    resource.binding
+                ),
+            }
+        }
+        Ok(())
+    }
+
+    /// The stages the module has entry points for.
+    pub fn stages(&self) -> wgpu::ShaderStages {
+        self.entry_points
+            .iter()
+            .fold(wgpu::ShaderStages::empty(), |stages, entry_point| {
+                stages | shader_stage(entry_point.stage)
+            })
+    }
+
+    /// Entry points for `stage`, in the order they appear in the source.
+    pub fn entry_points(&self, stage: naga::ShaderStage) -> impl Iterator<Item = &EntryPoint> {
+        self.entry_points
+            .iter()
+            .filter(move |entry_point| entry_point.stage == stage)
+    }
+
+    /// Gets the entry point for `stage` called `name`, or the first one if no name is given.
+    pub fn entry_point(&self, stage: naga::ShaderStage, name: Option<&str>) -> Result<&EntryPoint> {
+        let mut entry_points = self.entry_points(stage);
+        match name {
+            Some(name) => entry_points.find(|entry_point| entry_point.name == name),
+            None => entry_points.next(),
+        }
+        .ok_or_else(|| match name {
+            Some(name) => anyhow!("Shader has no {stage:?} entry point named '{name}'"),
+            None => anyhow!("Shader has no {stage:?} entry point"),
+        })
+    }
+
+    /// How many bind groups a pipeline layout needs, up to the last one with bindings.
+    pub fn bind_group_count(&self) -> usize {
+        self.bind_groups
+            .iter()
+            .rposition(|group| !group.is_empty())
+            .map_or(0, |group| group + 1)
+    }
+
+    /// The layout entries of bind group `group`.
+    pub fn layout_entries(&self, group: usize) -> Vec<wgpu::BindGroupLayoutEntry> {
+        self.bind_groups[group]
+            .iter()
+            .map(|binding| binding.entry)
+            .collect()
+    }
+}
+
+/// The `@location` inputs or outputs among `values`, looking inside structs. Built-ins are
+/// skipped.
+fn stage_io<'a>(
+    module: &naga::Module,
+    values: impl Iterator<
+        Item = (
+            Option<&'a String>,
+            naga::Handle<naga::Type>,
+            &'a Option<naga::Binding>,
+        ),
+    >,
+) -> Result<Vec<StageIo>> {
+    let mut io = vec![];
+    let mut push = |name: Option<&String>, location, ty: naga::Handle<naga::Type>| -> Result<()> {
+        let ty = IoType::from_naga(&module.types[ty].inner)
+            .ok_or_else(|| anyhow!("@location({location}) is not a scalar or vector"))?;
+        io.push(StageIo {
+            name: name.cloned(),
+            location,
+            ty,
+        });
+        Ok(())
+    };
+
+    for (name, ty, binding) in values {
+        match binding {
+            Some(naga::Binding::Location { location, .. }) => push(name, *location, ty)?,
+            Some(naga::Binding::BuiltIn(..)) => {}
+            None => match &module.types[ty].inner {
+                TypeInner::Struct { members, .. } => {
+                    for member in members {
+                        if let Some(naga::Binding::Location { location, .. }) = member.binding {
+                            push(member.name.as_ref(), location, member.ty)?;
+                        }
+                    }
+                }
+                _ => bail!("without a binding is not a struct"),
+            },
+        }
+    }
+    Ok(io)
+}
+
+impl IoType {
+    pub fn from_naga(ty: &TypeInner) -> Option<Self> {
+        match *ty {
+            TypeInner::Scalar { kind, width } => Some(Self {
+                kind,
+                width,
+                components: 1,
+            }),
+            TypeInner::Vector { size, kind, width } => Some(Self {
+                kind,
+                width,
+                components: size as u8,
+            }),
+            _ => None,
+        }
+    }
+
+    /// The type a vertex attribute of `format` has in the shader.
+    pub fn from_vertex_format(format: wgpu::VertexFormat) -> Self {
+        use naga::ScalarKind::*;
+        use wgpu::VertexFormat as F;
+
+        let (kind, width) = match format {
+            F::Uint8x2 | F::Uint8x4 | F::Uint16x2 | F::Uint16x4 => (Uint, 4),
+            F::Uint32 | F::Uint32x2 | F::Uint32x3 | F::Uint32x4 => (Uint, 4),
+            F::Sint8x2 | F::Sint8x4 | F::Sint16x2 | F::Sint16x4 => (Sint, 4),
+            F::Sint32 | F::Sint32x2 | F::Sint32x3 | F::Sint32x4 => (Sint, 4),
+            F::Float64 | F::Float64x2 | F::Float64x3 | F::Float64x4 => (Float, 8),
+            _ => (Float, 4),
+        };
+        let components = match format {
+            F::Float32 | F::Uint32 | F::Sint32 | F::Float64 => 1,
+            F::Float32x3 | F::Uint32x3 | F::Sint32x3 | F::Float64x3 => 3,
+            F::Uint8x4 | F::Sint8x4 | F::Unorm8x4 | F::Snorm8x4 => 4,
+            F::Uint16x4 | F::Sint16x4 | F::Unorm16x4 | F::Snorm16x4 | F::Float16x4 => 4,
+            F::Float32x4 | F::Uint32x4 | F::Sint32x4 | F::Float64x4 => 4,
+            _ => 2,
+        };
+        Self {
+            kind,
+            width,
+            components,
+        }
+    }
+
+    /// The vertex format that matches this type exactly.
+    pub fn vertex_format(&self) -> Result<wgpu::VertexFormat> {
+        use naga::ScalarKind::*;
+        use wgpu::VertexFormat as F;
+
+        Ok(match (self.kind, self.width, self.components) {
+            (Float, 4, 1) => F::Float32,
+            (Float, 4, 2) => F::Float32x2,
+            (Float, 4, 3) => F::Float32x3,
+            (Float, 4, 4) => F::Float32x4,
+            (Float, 8, 1) => F::Float64,
+            (Float, 8, 2) => F::Float64x2,
+            (Float, 8, 3) => F::Float64x3,
+            (Float, 8, 4) => F::Float64x4,
+            (Uint, _, 1) => F::Uint32,
+            (Uint, _, 2) => F::Uint32x2,
+            (Uint, _, 3) => F::Uint32x3,
+            (Uint, _, 4) => F::Uint32x4,
+            (Sint, _, 1) => F::Sint32,
+            (Sint, _, 2) => F::Sint32x2,
+            (Sint, _, 3) => F::Sint32x3,
+            (Sint, _, 4) => F::Sint32x4,
+            _ => bail!("no vertex format for {self}"),
+        })
+    }
+}
+
+impl fmt::Display for IoType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scalar = match (self.kind, self.width) {
+            (naga::ScalarKind::Float, 8) => "f64",
+            (naga::ScalarKind::Float, _) => "f32",
+            (naga::ScalarKind::Uint, _) => "u32",
+            (naga::ScalarKind::Sint, _) => "i32",
+            (naga::ScalarKind::Bool, _) => "bool",
+        };
+        match self.components {
+            1 => write!(f, "{scalar}"),
+            n => write!(f, "vec{n}<{scalar}>"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TEST_SHADER: &str = "
+        struct Vertex {
+            @location(0) pos: vec2<f32>,
+            //@location(1) tex: vec2<f32>,
+            @location(1) col: vec4<u32>
+        }
+        
+        struct Fragment {
+            @builtin(position) pos: vec4<f32>,
+            //@location(0) tex: vec2<f32>
+            @location(1) col: vec4<f32>
+        }
+        
+        struct Output {
+            @location(0) diffuse: vec4<f32>
+        }
+        
+        @group(0) @binding(0)
+        var texture: texture_2d<f32>;
+        @group(0) @binding(1)
+        var tex_sampler: sampler;
+        @group(1) @binding(0)
+        var<uniform> matrix: mat4x4<f32>;
+        
+        
+        // super simple vertex shader
+        @vertex
+        fn vertex(vert: Vertex) -> Fragment {
+            var frag: Fragment;
+            //frag.tex = vert.tex;
+            frag.pos = vec4<f32>(vert.pos, 1.0, 1.0);
+            frag.col = vec4<f32>(vert.col);
+            return frag;
+        }
+
+        @fragment
+        fn fragment(frag: Fragment) -> Output {
+            //return textureSample(texture, tex_sampler, frag.tex);
+            var output: Output;
+            output.diffuse = vec4<f32>(1.0, 1.0, 1.0, 1.0) * frag.col;
+            return output;
+        }
+    ";
+
+    fn reflect(source: &str) -> ShaderReflection {
+        let module = naga::front::wgsl::parse_str(source).unwrap();
+        ShaderReflection::new(&module).unwrap()
+    }
+
+    #[test]
+    fn serde() {
+        let reflection = reflect(include_str!("../../shaders/main.wgsl"));
+        let json = serde_json::to_string(&reflection).unwrap();
+        assert_eq!(
+            serde_json::from_str::<ShaderReflection>(&json).unwrap(),
+            reflection
+        );
+    }
+
+    #[test]
+    fn outputs() {
+        let reflection = reflect(TEST_SHADER);
+        let fragment = reflection
+            .entry_point(naga::ShaderStage::Fragment, None)
+            .unwrap();
+        assert_eq!(
+            fragment.outputs,
+            &[StageIo {
+                name: Some("diffuse".to_owned()),
+                location: 0,
+                ty: IoType {
+                    kind: naga::ScalarKind::Float,
+                    width: 4,
+                    components: 4,
+                },
+            }]
+        );
+    }
+
+    #[test]
+    fn stages() {
+        let reflection = reflect(TEST_SHADER);
+        assert_eq!(reflection.stages(), wgpu::ShaderStages::VERTEX_FRAGMENT)
+    }
+
+    #[test]
+    fn entry_points() {
+        let reflection = reflect(&format!(
+            "{TEST_SHADER}
+            @fragment
+            fn fragment_white(frag: Fragment) -> Output {{
+                var output: Output;
+                output.diffuse = vec4<f32>(1.0);
+                return output;
+            }}"
+        ));
+        let names = |stage| {
+            reflection
+                .entry_points(stage)
+                .map(|entry_point| entry_point.name.as_str())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(names(naga::ShaderStage::Vertex), &["vertex"]);
+        assert_eq!(
+            names(naga::ShaderStage::Fragment),
+            &["fragment", "fragment_white"]
+        );
+        assert!(names(naga::ShaderStage::Compute).is_empty());
+        assert!(reflection
+            .entry_point(naga::ShaderStage::Fragment, Some("fragment_black"))
+            .is_err());
+    }
+
+    #[test]
+    fn inputs() {
+        let reflection = reflect(TEST_SHADER);
+        let vertex = reflection
+            .entry_point(naga::ShaderStage::Vertex, None)
+            .unwrap();
+        let inputs = vertex
+            .inputs
+            .iter()
+            .map(|input| (input.location, input.ty.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            inputs,
+            &[(0, "vec2<f32>".to_owned()), (1, "vec4<u32>".to_owned())]
+        );
+    }
+
+    #[test]
+    fn buffers() {
+        let mut reflection = reflect(&format!(
+            "{TEST_SHADER}
+            struct Light {{
+                pos: vec3<f32>,
+                radius: f32,
+                col: vec4<f32>,
+            }}
+            @group(2) @binding(0)
+            var<storage, read> lights: array<Light>;
+            @group(2) @binding(1)
+            var<storage, read_write> visible: array<u32>;
+
+            @compute @workgroup_size(64)
+            fn cull(@builtin(global_invocation_id) id: vec3<u32>) {{
+                visible[id.x] = u32(lights[id.x].radius > 0.0);
+            }}"
+        ));
+
+        let dynamic = naga::ResourceBinding {
+            group: 1,
+            binding: 0,
+        };
+        reflection.set_dynamic_offsets(&[dynamic]).unwrap();
+        let uniforms = reflection.layout_entries(1);
+        let storage = reflection.layout_entries(2);
+        assert_eq!(
+            uniforms[0].ty,
+            wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: true,
+                min_binding_size: wgpu::BufferSize::new(64),
+            }
+        );
+        assert_eq!(
+            storage[0].ty,
+            wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: wgpu::BufferSize::new(32),
+            }
+        );
+        assert_eq!(
+            storage[1].ty,
+            wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: wgpu::BufferSize::new(4),
+            }
+        );
+        assert_eq!(storage[0].visibility, wgpu::ShaderStages::COMPUTE);
+
+        let texture = naga::ResourceBinding {
+            group: 0,
+            binding: 0,
+        };
+        assert!(reflection.set_dynamic_offsets(&[texture]).is_err());
+
+        let cull = reflection
+            .entry_point(naga::ShaderStage::Compute, None)
+            .unwrap();
+        assert_eq!(cull.workgroup_size, [64, 1, 1]);
+    }
+
+    #[test]
+    fn textures() {
+        let reflection = reflect(
+            "
+            @group(0) @binding(0)
+            var shadow_map: texture_depth_2d;
+            @group(0) @binding(1)
+            var shadow_sampler: sampler_comparison;
+            @group(0) @binding(2)
+            var output: texture_storage_2d<rgba16float, write>;
+
+            @compute @workgroup_size(8, 8)
+            fn shade(@builtin(global_invocation_id) id: vec3<u32>) {
+                let uv = vec2<f32>(id.xy) / 512.0;
+                let lit = textureSampleCompareLevel(shadow_map, shadow_sampler, uv, 0.5);
+                textureStore(output, id.xy, vec4<f32>(lit));
+            }",
+        );
+
+        let entries = reflection.layout_entries(0);
+        assert_eq!(
+            entries[0].ty,
+            wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Depth,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            }
+        );
+        assert_eq!(
+            entries[1].ty,
+            wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison)
+        );
+        assert_eq!(
+            entries[2].ty,
+            wgpu::BindingType::StorageTexture {
+                access: wgpu::StorageTextureAccess::WriteOnly,
+                format: wgpu::TextureFormat::Rgba16Float,
+                view_dimension: wgpu::TextureViewDimension::D2,
+            }
+        );
+    }
+
+    #[test]
+    fn visibility_through_calls() {
+        let reflection = reflect(
+            "
+            @group(0) @binding(0)
+            var<uniform> tint: vec4<f32>;
+
+            fn shade(color: vec4<f32>) -> vec4<f32> {
+                return color * tint;
+            }
+
+            fn lit(color: vec4<f32>) -> vec4<f32> {
+                if color.a > 0.0 {
+                    return shade(color);
+                }
+                return color;
+            }
+
+            @vertex
+            fn vertex() -> @builtin(position) vec4<f32> {
+                return vec4<f32>();
+            }
+
+            @fragment
+            fn fragment() -> @location(0) vec4<f32> {
+                return lit(vec4<f32>(1.0));
+            }",
+        );
+        assert_eq!(
+            reflection.layout_entries(0)[0].visibility,
+            wgpu::ShaderStages::FRAGMENT
+        );
+    }
+
+    #[test]
+    fn binding_arrays() {
+        let reflection = reflect(
+            "
+            @group(0) @binding(0)
+            var atlases: binding_array<texture_2d<f32>, 16>;
+            @group(0) @binding(1)
+            var atlas_sampler: sampler;
+
+            struct Fragment {
+                @builtin(position) pos: vec4<f32>,
+                @location(0) tex: vec2<f32>,
+                @location(1) @interpolate(flat) atlas: u32,
+            }
+
+            @fragment
+            fn fragment(frag: Fragment) -> @location(0) vec4<f32> {
+                return textureSample(atlases[frag.atlas], atlas_sampler, frag.tex);
+            }",
+        );
+
+        let entries = reflection.layout_entries(0);
+        assert_eq!(entries[0].count, std::num::NonZeroU32::new(16));
+        assert_eq!(
+            entries[0].ty,
+            wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            }
+        );
+        assert_eq!(entries[1].count, None);
+        assert_eq!(
+            reflection.bind_groups[0][0].name.as_deref(),
+            Some("atlases")
+        );
+        // the atlas can differ between fragments.
+        assert_eq!(
+            reflection.features,
+            wgpu::Features::TEXTURE_BINDING_ARRAY
+                | wgpu::Features::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING
+        );
+
+        let reflection = reflect(
+            "
+            @group(0) @binding(0)
+            var atlases: binding_array<texture_2d<f32>, 16>;
+            @group(0) @binding(1)
+            var atlas_sampler: sampler;
+            @group(0) @binding(2)
+            var<uniform> atlas: u32;
+
+            @fragment
+            fn fragment(@location(0) tex: vec2<f32>) -> @location(0) vec4<f32> {
+                return textureSample(atlases[atlas], atlas_sampler, tex);
+            }",
+        );
+        assert_eq!(reflection.features, wgpu::Features::TEXTURE_BINDING_ARRAY);
+
+        let reflection = reflect(TEST_SHADER);
+        assert!(reflection.features.is_empty());
+    }
+
+    #[test]
+    fn filterability() {
+        let reflection = reflect(
+            "
+            @group(0) @binding(0)
+            var diffuse: texture_2d<f32>;
+            @group(0) @binding(1)
+            var diffuse_sampler: sampler;
+            @group(0) @binding(2)
+            var heights: texture_2d<f32>;
+            @group(0) @binding(3)
+            var shadow_map: texture_depth_2d;
+            @group(0) @binding(4)
+            var shadow_sampler: sampler;
+
+            @fragment
+            fn fragment(@builtin(position) pos: vec4<f32>) -> @location(0) vec4<f32> {
+                let uv = pos.xy / 512.0;
+                let height = textureLoad(heights, vec2<i32>(pos.xy), 0).r;
+                let shadow = textureSample(shadow_map, shadow_sampler, uv);
+                return textureSample(diffuse, diffuse_sampler, uv) * height * shadow;
+            }",
+        );
+
+        let entries = reflection.layout_entries(0);
+        let sample_type = |entry: &wgpu::BindGroupLayoutEntry| match entry.ty {
+            wgpu::BindingType::Texture { sample_type, .. } => sample_type,
+            ty => panic!("{ty:?} is not a texture"),
+        };
+        assert_eq!(
+            sample_type(&entries[0]),
+            wgpu::TextureSampleType::Float { filterable: true }
+        );
+        assert_eq!(
+            entries[1].ty,
+            wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering)
+        );
+        assert_eq!(
+            sample_type(&entries[2]),
+            wgpu::TextureSampleType::Float { filterable: false }
+        );
+        assert_eq!(sample_type(&entries[3]), wgpu::TextureSampleType::Depth);
+        assert_eq!(
+            entries[4].ty,
+            wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering)
+        );
+    }
+}
@@ -0,0 +1,453 @@
+use std::fmt;
+
+use itertools::Itertools;
+
+use anyhow::{ensure, Context, Result};
+use naga::{
+    valid::{Capabilities, ValidationFlags, Validator},
+    TypeInner,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{EntryPoint, ShaderReflection};
+
+/// The layout of the engine's `Vertex` type, which every vertex entry point reads from.
+pub const VERTEX_LAYOUT: wgpu::VertexBufferLayout<'static> = wgpu::VertexBufferLayout {
+    array_stride: 24,
+    step_mode: wgpu::VertexStepMode::Vertex,
+    attributes: &wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Uint8x4, 3 => Uint32],
+};
+
+/// The most color attachments a framebuffer can have, as per the spec.
+pub const MAX_COLOR_ATTACHMENTS: usize = 8;
+
+/// Size of the transform matrix the engine binds, a `mat4x4<f32>`.
+const MATRIX_SIZE: u64 = 64;
+
+/// Everything `purs` can tell about a WGSL shader, along with why the engine would refuse it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Report {
+    pub path: String,
+    pub reflection: Option<ShaderReflection>,
+    pub structs: Vec<StructLayout>,
+    pub errors: Vec<String>,
+    /// Things that aren't errors, but need care when drawing with the shader.
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StructLayout {
+    pub name: String,
+    pub size: u32,
+    pub alignment: u32,
+    pub members: Vec<MemberLayout>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemberLayout {
+    pub name: Option<String>,
+    pub ty: String,
+    pub offset: u32,
+    pub size: u32,
+}
+
+impl Report {
+    /// Parses, validates and reflects WGSL `source`, collecting every error instead of stopping
+    /// at the first.
+    pub fn new(path: impl Into<String>, source: &str) -> Self {
+        let mut report = Self {
+            path: path.into(),
+            reflection: None,
+            structs: vec![],
+            errors: vec![],
+            warnings: vec![],
+        };
+
+        let module = match naga::front::wgsl::parse_str(source) {
+            Ok(module) => module,
+            Err(e) => {
+                report.errors.push(e.emit_to_string(source));
+                return report;
+            }
+        };
+        if let Err(e) =
+            Validator::new(ValidationFlags::all(), Capabilities::all()).validate(&module)
+        {
+            report.errors.push(e.emit_to_string(source));
+            return report;
+        }
+
+        match struct_layouts(&module) {
+            Ok(structs) => report.structs = structs,
+            Err(e) => report.errors.push(format!("{e:#}")),
+        }
+        match ShaderReflection::new(&module) {
+            Ok(reflection) => {
+                report
+                    .errors
+                    .extend(check(&reflection).into_iter().map(|e| format!("{e:#}")));
+                report.warnings = unbound_bindings(&reflection);
+                report.reflection = Some(reflection);
+            }
+            Err(e) => report.errors.push(format!("{e:#}")),
+        }
+        report
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// Checks `reflection` against what the engine needs to build a render pipeline from it.
+pub fn check(reflection: &ShaderReflection) -> Vec<anyhow::Error> {
+    let mut errors = vec![];
+
+    for stage in [naga::ShaderStage::Vertex, naga::ShaderStage::Fragment] {
+        if let Err(e) = reflection.entry_point(stage, None) {
+            errors.push(e);
+        }
+    }
+    for entry_point in reflection.entry_points(naga::ShaderStage::Vertex) {
+        if let Err(e) = crate::gen::vertex_attributes(entry_point, &VERTEX_LAYOUT) {
+            errors.push(e.context("Shader inputs don't match the Vertex type"));
+        }
+    }
+    for entry_point in reflection.entry_points(naga::ShaderStage::Fragment) {
+        if let Err(e) = check_outputs(entry_point) {
+            errors.push(e);
+        }
+    }
+    errors
+}
+
+/// The bindings of `reflection` the engine doesn't bind by itself, see [`builtin_binding`].
+/// They're fine as long as something is set there for every batch drawn with the shader.
+pub fn unbound_bindings(reflection: &ShaderReflection) -> Vec<String> {
+    reflection
+        .bind_groups
+        .iter()
+        .enumerate()
+        .flat_map(|(group, bindings)| bindings.iter().map(move |binding| (group, binding)))
+        .filter(|(group, binding)| !builtin_binding(*group, &binding.entry))
+        .map(|(group, binding)| {
+            format!(
+                "@group({group}) @binding({}) {} is a {}, which the engine doesn't bind, so it has to be set for every batch drawn with the shader",
+                binding.entry.binding,
+                binding.name.as_deref().unwrap_or("_"),
+                binding_type(&binding.entry.ty)
+            )
+        })
+        .collect()
+}
+
+/// Whether the engine binds something to `entry` when nothing else is set there: a texture
+/// at `@group(0) @binding(0)`, a sampler at `@group(0) @binding(1)` and the transform matrix
+/// at `@group(1) @binding(0)`.
+pub fn builtin_binding(group: usize, entry: &wgpu::BindGroupLayoutEntry) -> bool {
+    entry.count.is_none()
+        && match (group, entry.binding, entry.ty) {
+            (
+                0,
+                0,
+                wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { .. },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+            ) => true,
+            (0, 1, wgpu::BindingType::Sampler(ty)) => ty != wgpu::SamplerBindingType::Comparison,
+            (
+                1,
+                0,
+                wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    min_binding_size,
+                    ..
+                },
+            ) => min_binding_size.map_or(0, |size| size.get()) <= MATRIX_SIZE,
+            _ => false,
+        }
+}
+
+fn check_outputs(entry_point: &EntryPoint) -> Result<()> {
+    for output in &entry_point.outputs {
+        ensure!(
+            (output.location as usize) < MAX_COLOR_ATTACHMENTS,
+            "fragment output @location({}) of '{}' is past the last color attachment",
+            output.location,
+            entry_point.name
+        );
+    }
+    Ok(())
+}
+
+/// The layout of every named struct in `module`.
+fn struct_layouts(module: &naga::Module) -> Result<Vec<StructLayout>> {
+    let mut layouter = naga::proc::Layouter::default();
+    layouter
+        .update(module.to_ctx())
+        .context("Could not lay out the shader's types")?;
+
+    Ok(module
+        .types
+        .iter()
+        .filter_map(|(handle, ty)| {
+            let TypeInner::Struct { members, span } = &ty.inner else {
+                return None;
+            };
+            Some(StructLayout {
+                name: ty.name.clone().unwrap_or_default(),
+                size: *span,
+                // rounding 1 up gives the alignment itself.
+                alignment: layouter[handle].alignment.round_up(1),
+                members: members
+                    .iter()
+                    .map(|member| MemberLayout {
+                        name: member.name.clone(),
+                        ty: crate::type_string(module, member.ty),
+                        offset: member.offset,
+                        size: layouter[member.ty].size,
+                    })
+                    .collect(),
+            })
+        })
+        .collect())
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.path)?;
+
+        if let Some(reflection) = &self.reflection {
+            for entry_point in &reflection.entry_points {
+                let stage = format!("{:?}", entry_point.stage).to_lowercase();
+                writeln!(f, "  @{stage} fn {}", entry_point.name)?;
+                if entry_point.stage == naga::ShaderStage::Compute {
+                    let [x, y, z] = entry_point.workgroup_size;
+                    writeln!(f, "    workgroup size ({x}, {y}, {z})")?;
+                }
+                for (direction, values) in
+                    [("in ", &entry_point.inputs), ("out", &entry_point.outputs)]
+                {
+                    for value in values {
+                        writeln!(
+                            f,
+                            "    {direction} @location({}) {}: {}",
+                            value.location,
+                            value.name.as_deref().unwrap_or("_"),
+                            value.ty
+                        )?;
+                    }
+                }
+            }
+
+            for (group, bindings) in reflection.bind_groups.iter().enumerate() {
+                if bindings.is_empty() {
+                    continue;
+                }
+                writeln!(f, "  @group({group})")?;
+                for binding in bindings {
+                    let count = binding
+                        .entry
+                        .count
+                        .map(|count| format!(" x{count}"))
+                        .unwrap_or_default();
+                    writeln!(
+                        f,
+                        "    @binding({}) {}: {}{count} ({})",
+                        binding.entry.binding,
+                        binding.name.as_deref().unwrap_or("_"),
+                        binding_type(&binding.entry.ty),
+                        binding
+                            .entry
+                            .visibility
+                            .iter_names()
+                            .map(|(name, _)| name.to_lowercase())
+                            .join(", ")
+                    )?;
+                }
+            }
+            if !reflection.features.is_empty() {
+                writeln!(f, "  needs {:?}", reflection.features)?;
+            }
+        }
+
+        for layout in &self.structs {
+            writeln!(
+                f,
+                "  struct {} (size {}, align {})",
+                layout.name, layout.size, layout.alignment
+            )?;
+            for member in &layout.members {
+                writeln!(
+                    f,
+                    "    {:>4} {}: {} (size {})",
+                    member.offset,
+                    member.name.as_deref().unwrap_or("_"),
+                    member.ty,
+                    member.size
+                )?;
+            }
+        }
+
+        for error in &self.errors {
+            writeln!(f, "  error: {error}")?;
+        }
+        for warning in &self.warnings {
+            writeln!(f, "  warning: {warning}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A short description of a binding type, e.g. "D2 filterable float texture".
+pub fn binding_type(ty: &wgpu::BindingType) -> String {
+    match *ty {
+        wgpu::BindingType::Buffer {
+            ty,
+            has_dynamic_offset,
+            min_binding_size,
+        } => {
+            let mut s = match ty {
+                wgpu::BufferBindingType::Uniform => "uniform buffer".to_owned(),
+                wgpu::BufferBindingType::Storage { read_only: true } => {
+                    "read-only storage buffer".to_owned()
+                }
+                wgpu::BufferBindingType::Storage { read_only: false } => {
+                    "storage buffer".to_owned()
+                }
+            };
+            if let Some(size) = min_binding_size {
+                s += &format!(", at least {size} bytes");
+            }
+            if has_dynamic_offset {
+                s += ", dynamic offset";
+            }
+            s
+        }
+        wgpu::BindingType::Sampler(ty) => format!("{ty:?} sampler").to_lowercase(),
+        wgpu::BindingType::Texture {
+            sample_type,
+            view_dimension,
+            multisampled,
+        } => {
+            let sample_type = match sample_type {
+                wgpu::TextureSampleType::Float { filterable: true } => "filterable float",
+                wgpu::TextureSampleType::Float { filterable: false } => "unfilterable float",
+                wgpu::TextureSampleType::Depth => "depth",
+                wgpu::TextureSampleType::Sint => "sint",
+                wgpu::TextureSampleType::Uint => "uint",
+            };
+            let multisampled = if multisampled { "multisampled " } else { "" };
+            format!("{multisampled}{view_dimension:?} {sample_type} texture")
+        }
+        wgpu::BindingType::StorageTexture {
+            access,
+            format,
+            view_dimension,
+        } => format!("{view_dimension:?} {format:?} storage texture, {access:?}"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn main_shader() {
+        let report = Report::new("main.wgsl", include_str!("../../shaders/main.wgsl"));
+        assert!(report.is_ok(), "{:?}", report.errors);
+
+        let fragment = report
+            .structs
+            .iter()
+            .find(|layout| layout.name == "Fragment")
+            .unwrap();
+        assert_eq!((fragment.size, fragment.alignment), (48, 16));
+        assert_eq!(
+            fragment
+                .members
+                .iter()
+                .map(|member| (member.ty.as_str(), member.offset, member.size))
+                .collect::<Vec<_>>(),
+            [
+                ("vec4<f32>", 0, 16),
+                ("vec2<f32>", 16, 8),
+                ("vec4<f32>", 32, 16)
+            ]
+        );
+
+        let text = report.to_string();
+        assert!(text.contains(
+            "@group(1)\n    @binding(0) matrix: uniform buffer, at least 64 bytes (vertex)"
+        ));
+        assert!(text.contains("texture: D2 filterable float texture (fragment)"));
+    }
+
+    #[test]
+    fn bindings() {
+        let reflect = |source: &str| ShaderReflection::from_wgsl(source).unwrap();
+        assert!(unbound_bindings(&reflect(include_str!("../../shaders/main.wgsl"))).is_empty());
+        // the engine's bindings can be left out.
+        assert!(unbound_bindings(&reflect(
+            "@group(1) @binding(0) var<uniform> matrix: mat4x4<f32>;
+            @vertex fn main() -> @builtin(position) vec4<f32> { return matrix[0]; }"
+        ))
+        .is_empty());
+
+        let warnings = unbound_bindings(&reflect(
+            "@group(0) @binding(2) var<uniform> tint: vec4<f32>;
+            @fragment fn main() -> @location(0) vec4<f32> { return tint; }",
+        ));
+        assert_eq!(warnings.len(), 1);
+        assert!(
+            warnings[0].starts_with("@group(0) @binding(2) tint is a uniform buffer, at least 16 bytes, which the engine doesn't bind"),
+            "{}",
+            warnings[0]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let report = Report::new("broken.wgsl", "fn main() -> f32 { return 1u; }");
+        assert!(!report.is_ok());
+        assert!(report.reflection.is_none());
+
+        let report = Report::new(
+            "compute.wgsl",
+            "@compute @workgroup_size(1) fn main(@builtin(global_invocation_id) id: vec3<u32>) {}",
+        );
+        assert_eq!(
+            report.errors,
+            [
+                "Shader has no Vertex entry point",
+                "Shader has no Fragment entry point"
+            ]
+        );
+
+        let report = Report::new(
+            "vertex.wgsl",
+            "@vertex fn vertex(@location(1) col: vec4<f32>) -> @builtin(position) vec4<f32> { return col; }
+            @fragment fn fragment() -> @location(8) vec4<f32> { return vec4<f32>(); }",
+        );
+        assert_eq!(
+            report.errors,
+            [
+                "Shader inputs don't match the Vertex type: vertex input @location(1) of 'vertex' is vec4<f32>, but the vertex type provides vec2<f32> (Float32x2)",
+                "fragment output @location(8) of 'fragment' is past the last color attachment"
+            ]
+        );
+
+        let report = Report::new(
+            "bindings.wgsl",
+            "@group(2) @binding(0) var<uniform> time: f32;
+            @vertex fn vertex(@location(0) pos: vec2<f32>) -> @builtin(position) vec4<f32> { return vec4<f32>(pos, time, 1.0); }
+            @fragment fn fragment() -> @location(0) vec4<f32> { return vec4<f32>(); }",
+        );
+        // bindings the engine doesn't know about can still be set for each batch.
+        assert!(report.is_ok());
+        assert_eq!(report.warnings.len(), 1);
+        assert!(report.warnings[0].starts_with("@group(2) @binding(0) time is a uniform buffer"));
+    }
+}
@@ -1,6 +1,6 @@
 struct Fragment {
     @builtin(position) pos: vec4<f32>,
-    //@location(0) tex: vec2<f32>
+    @location(0) tex: vec2<f32>,
     @location(1) col: vec4<f32>
 }
 
@@ -20,16 +20,16 @@ var<uniform> matrix: mat4x4<f32>;
 @vertex
 fn vertex(@location(0) pos: vec2<f32>, @location(1) tex_pos: vec2<f32>, @location(2) col: vec4<u32>) -> Fragment {
     var frag: Fragment;
-    //frag.tex = vert.tex;
-    frag.pos = vec4<f32>(pos, 1.0, 1.0);
-    frag.col = vec4<f32>(col);
+    frag.tex = tex_pos;
+    frag.pos = matrix * vec4<f32>(pos, 0.0, 1.0);
+    // vertex colours are packed as bytes.
+    frag.col = vec4<f32>(col) / 255.0;
     return frag;
 }
 
 @fragment
 fn fragment(frag: Fragment) -> Output {
-    //return textureSample(texture, tex_sampler, frag.tex);
     var output: Output;
-    output.diffuse = vec4<f32>(1.0, 1.0, 1.0, 1.0) * frag.col;
+    output.diffuse = textureSample(texture, tex_sampler, frag.tex) * frag.col;
     return output;
 }
\ No newline at end of file
@@ -4,9 +4,14 @@
 use anyhow::Result;
 use glam::UVec2;
 use internal::{Internal, InternalData};
-use log::info;
+use log::{info, warn};
 use window::*;
 
+/// Reflection of the shaders loaded last run, so unchanged ones skip parsing on startup.
+const REFLECTION_CACHE: &str = "shaders/reflection.json";
+/// Read by debug builds when it exists, so edits to the main shader show up while running.
+const MAIN_SHADER: &str = "shaders/main.wgsl";
+
 pub struct KittenGame {
     window: KittenWindow,
     internal_renderer: Internal,
@@ -22,7 +27,10 @@ impl KittenGame {
         // initialize important stuff.
         let (window, window_loop) = window::KittenWindow::new(title, window_size)?;
 
-        let internal_renderer = Internal::new(&window)?;
+        let mut internal_renderer = Internal::new(&window)?;
+        if cfg!(debug_assertions) {
+            internal_renderer.set_main_shader_path(Some(MAIN_SHADER));
+        }
         let internal_graphics_data = InternalData::default();
         // mouse handling / gamepad handling / keyboard handling (input)
         // audio??
@@ -46,10 +54,23 @@ impl KittenGame {
     }
     fn init(&mut self) -> Result<()> {
         self.window.set_resizable(true);
+        // the cache only saves time, so the game runs without it.
+        if let Err(e) = self
+            .internal_graphics_data
+            .load_reflection_cache(REFLECTION_CACHE)
+        {
+            warn!("{e:#}");
+        }
         Ok(())
     }
     fn quit(&mut self) -> Result<()> {
         info!("quitting!");
+        if let Err(e) = self
+            .internal_graphics_data
+            .save_reflection_cache(REFLECTION_CACHE)
+        {
+            warn!("{e:#}");
+        }
         Ok(())
     }
     fn update(&mut self) -> Result<()> {
@@ -64,7 +85,7 @@ impl KittenGame {
         // 2. create / set pipeline
         // 3. draw
         self.internal_renderer
-            .render(&self.internal_graphics_data, self.window_size)?;
+            .render(&mut self.internal_graphics_data, self.window_size)?;
         Ok(())
     }
 }